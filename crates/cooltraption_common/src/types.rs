@@ -1,5 +1,15 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use serde::{Deserialize, Serialize};
 
+/// Stable id of a player, assigned by the server when joining a room
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub struct PlayerId(pub u64);
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct TimePoint(u128);
 impl TimePoint {
@@ -7,7 +17,46 @@ impl TimePoint {
         Self(millis)
     }
 
+    /// Current time of the local system clock
+    pub fn now() -> Self {
+        Self(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+        )
+    }
+
     pub fn millis(&self) -> u128 {
         self.0
     }
+
+    pub fn offset_by(&self, offset_millis: i64) -> Self {
+        Self((self.0 as i128 + offset_millis as i128).max(0) as u128)
+    }
+}
+
+/// Local clock corrected by an estimated offset to a remote clock, shared between threads
+#[derive(Debug, Clone, Default)]
+pub struct SyncedClock {
+    offset_millis: Arc<AtomicI64>,
+}
+
+impl SyncedClock {
+    pub fn now(&self) -> TimePoint {
+        TimePoint::now().offset_by(self.offset_millis())
+    }
+
+    pub fn offset_millis(&self) -> i64 {
+        self.offset_millis.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offset_millis(&self, offset_millis: i64) {
+        self.offset_millis.store(offset_millis, Ordering::Relaxed);
+    }
+
+    pub fn duration_until(&self, time_point: TimePoint) -> Duration {
+        let millis = time_point.millis().saturating_sub(self.now().millis());
+        Duration::from_millis(millis as u64)
+    }
 }
@@ -3,6 +3,12 @@ use std::sync::Mutex;
 
 use message_io::node;
 use message_io::node::NodeListener;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::Codec;
+use crate::codec::JsonCodec;
+use crate::conditions::NetworkConditions;
 
 use crate::network_state::ConcurrentNetworkState;
 use crate::network_state::NetworkStateEventHandler;
@@ -16,11 +22,14 @@ pub struct NodeEventHandlerBuilder<T> {
     pub node_listener: NodeListener<Signal>,
 }
 
-impl<T> Default for NodeEventHandlerBuilder<T> {
+impl<T: Serialize + DeserializeOwned + 'static> Default for NodeEventHandlerBuilder<T> {
     fn default() -> Self {
         let (node_handler, node_listener) = node::split::<Signal>();
         Self {
-            network_state: Arc::new(Mutex::new(NetworkStateImpl::new(node_handler))),
+            network_state: Arc::new(Mutex::new(NetworkStateImpl::new(
+                node_handler,
+                Arc::new(JsonCodec),
+            ))),
             network_state_publisher: vec![],
             node_listener,
         }
@@ -32,6 +41,22 @@ impl<T> NodeEventHandlerBuilder<T> {
         self.network_state_publisher.push(handler);
     }
 
+    /// Both ends of a connection have to use the same codec, defaults to `JsonCodec`
+    pub fn set_codec(&mut self, codec: impl Codec<T> + 'static) {
+        self.network_state
+            .lock()
+            .unwrap()
+            .set_codec(Arc::new(codec));
+    }
+
+    /// Only meant for testing how the game copes with a bad connection
+    pub fn set_network_conditions(&mut self, network_conditions: NetworkConditions) {
+        self.network_state
+            .lock()
+            .unwrap()
+            .set_network_conditions(Some(network_conditions));
+    }
+
     pub fn build(self) -> NodeEventHandler<T> {
         NodeEventHandler::new(
             self.network_state,
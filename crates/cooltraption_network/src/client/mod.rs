@@ -1,35 +1,130 @@
-use std::net::ToSocketAddrs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
 
-use log::debug;
-use serde::de::DeserializeOwned;
+use log::{debug, warn};
 
 use crate::network_state::NodeEventHandler;
+use crate::transport::Transport;
 
-pub fn connect<T>(server: impl ToSocketAddrs, node_event_handler: NodeEventHandler<T>)
-where
-    T: DeserializeOwned,
-{
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectPolicy {
+    #[default]
+    Never,
+    /// Gives up after `max_attempts` retries failed in a row
+    Retry {
+        max_attempts: u32,
+        delay: Duration,
+    },
+    Forever {
+        delay: Duration,
+    },
+}
+
+impl ReconnectPolicy {
+    /// Delay before the next retry, `failed_attempts` retries in a row have failed before it
+    pub fn delay_for(&self, failed_attempts: u32) -> Option<Duration> {
+        match *self {
+            ReconnectPolicy::Never => None,
+            ReconnectPolicy::Retry {
+                max_attempts,
+                delay,
+            } => (failed_attempts < max_attempts).then_some(delay),
+            ReconnectPolicy::Forever { delay } => Some(delay),
+        }
+    }
+}
+
+/// Connects to the server and handles events until the node handler is stopped. Lost and
+/// failed connections as well as failed address lookups are retried according to the
+/// `ReconnectPolicy`, the error is returned once the policy gives up on resolving the address.
+pub fn connect<T>(
+    server: impl ToSocketAddrs,
+    transport: Transport,
+    reconnect_policy: ReconnectPolicy,
+    node_event_handler: NodeEventHandler<T>,
+) -> io::Result<()> {
     debug!("Connecting");
+    let mut failed_attempts = 0;
+    let server = loop {
+        let error = match resolve(&server) {
+            Ok(server) => break server,
+            Err(e) => e,
+        };
+        let Some(delay) = reconnect_policy.delay_for(failed_attempts) else {
+            return Err(error);
+        };
+        failed_attempts += 1;
+        warn!(
+            "Could not resolve server address, retrying in {:?}: {}",
+            delay, error
+        );
+        thread::sleep(delay);
+        if !node_event_handler.node_handler().is_running() {
+            return Ok(());
+        }
+    };
     node_event_handler
-        .node_handler()
-        .network()
-        .connect(
-            message_io::network::Transport::FramedTcp,
-            server.to_socket_addrs().unwrap().next().unwrap(),
-        )
-        .expect("localhost to allow outgoing connections");
+        .network_state
+        .lock()
+        .unwrap()
+        .connect(server, transport, reconnect_policy);
 
     node_event_handler.handle_event_loop();
+    Ok(())
 }
 
-pub fn listen<T>(addr: impl ToSocketAddrs, node_event_handler: NodeEventHandler<T>)
-where
-    T: DeserializeOwned,
-{
+fn resolve(server: &impl ToSocketAddrs) -> io::Result<SocketAddr> {
+    server.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "server address did not resolve to any socket address",
+        )
+    })
+}
+
+/// Starts listening without handling any events yet and returns the address that was bound,
+/// which tells the actual port if port 0 was requested
+pub fn bind<T>(
+    addr: impl ToSocketAddrs,
+    transport: Transport,
+    node_event_handler: &NodeEventHandler<T>,
+) -> io::Result<SocketAddr> {
     node_event_handler
-        .node_handler()
-        .network()
-        .listen(message_io::network::Transport::FramedTcp, addr)
-        .unwrap();
+        .network_state
+        .lock()
+        .unwrap()
+        .listen(addr, transport)
+}
+
+pub fn listen<T>(
+    addr: impl ToSocketAddrs,
+    transport: Transport,
+    node_event_handler: NodeEventHandler<T>,
+) {
+    bind(addr, transport, &node_event_handler).unwrap();
     node_event_handler.handle_event_loop();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_policy_gives_up_after_max_attempts_retries() {
+        let delay = Duration::from_millis(10);
+        let policy = ReconnectPolicy::Retry {
+            max_attempts: 2,
+            delay,
+        };
+        assert_eq!(policy.delay_for(0), Some(delay));
+        assert_eq!(policy.delay_for(1), Some(delay));
+        assert_eq!(policy.delay_for(2), None);
+        assert_eq!(ReconnectPolicy::Never.delay_for(0), None);
+        assert_eq!(
+            ReconnectPolicy::Forever { delay }.delay_for(u32::MAX),
+            Some(delay)
+        );
+    }
+}
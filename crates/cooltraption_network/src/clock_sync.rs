@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use cooltraption_common::types::{SyncedClock, TimePoint};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Interval in which the connecting side pings the other side to refresh its clock estimate
+pub const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(2);
+
+const MAX_SAMPLES: usize = 8;
+
+/// NTP-style timestamps, every `TimePoint` is taken from the clock of the side that sent it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum TimeSyncPacket {
+    Ping {
+        client_send: TimePoint,
+    },
+    Pong {
+        client_send: TimePoint,
+        server_receive: TimePoint,
+        server_send: TimePoint,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClockSample {
+    pub offset_millis: i64,
+    pub rtt: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClockSync {
+    clock: SyncedClock,
+    samples: VecDeque<ClockSample>,
+    pinging: bool,
+}
+
+impl ClockSync {
+    /// The clock of the remote side as estimated by this side
+    pub fn clock(&self) -> SyncedClock {
+        self.clock.clone()
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.best_sample().map(|sample| sample.rtt)
+    }
+
+    pub fn ping(&self) -> TimeSyncPacket {
+        TimeSyncPacket::Ping {
+            client_send: TimePoint::now(),
+        }
+    }
+
+    /// Returns true only for the first call, so that only one ping timer is started
+    pub(crate) fn start_pinging(&mut self) -> bool {
+        !std::mem::replace(&mut self.pinging, true)
+    }
+
+    /// Answers pings and updates the clock estimate with pongs
+    pub fn handle(&mut self, packet: &TimeSyncPacket) -> Option<TimeSyncPacket> {
+        match *packet {
+            TimeSyncPacket::Ping { client_send } => {
+                let server_receive = self.clock.now();
+                Some(TimeSyncPacket::Pong {
+                    client_send,
+                    server_receive,
+                    server_send: self.clock.now(),
+                })
+            }
+            TimeSyncPacket::Pong {
+                client_send,
+                server_receive,
+                server_send,
+            } => {
+                let client_receive = TimePoint::now();
+                self.add_sample(client_send, server_receive, server_send, client_receive);
+                None
+            }
+        }
+    }
+
+    fn add_sample(
+        &mut self,
+        client_send: TimePoint,
+        server_receive: TimePoint,
+        server_send: TimePoint,
+        client_receive: TimePoint,
+    ) {
+        let [t0, t1, t2, t3] = [client_send, server_receive, server_send, client_receive]
+            .map(|time_point| time_point.millis() as i128);
+        let sample = ClockSample {
+            offset_millis: ((t1 - t0) + (t2 - t3)) as i64 / 2,
+            rtt: Duration::from_millis(((t3 - t0) - (t2 - t1)).max(0) as u64),
+        };
+
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+
+        // The sample with the lowest round trip time suffers the least from asymmetric delays
+        let best_sample = self.best_sample().expect("a sample was just added");
+        self.clock.set_offset_millis(best_sample.offset_millis);
+        debug!(
+            "Clock offset {}ms, rtt {}ms",
+            best_sample.offset_millis,
+            best_sample.rtt.as_millis()
+        );
+    }
+
+    fn best_sample(&self) -> Option<ClockSample> {
+        self.samples.iter().min_by_key(|sample| sample.rtt).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample of a remote clock that is `offset` ahead, with the given delays in both directions
+    fn add_sample(clock_sync: &mut ClockSync, offset: u128, to_remote: u128, from_remote: u128) {
+        let client_send = 10_000;
+        let server_receive = client_send + offset + to_remote;
+        let server_send = server_receive + 2;
+        let client_receive = server_send - offset + from_remote;
+        clock_sync.add_sample(
+            TimePoint::from_millis(client_send),
+            TimePoint::from_millis(server_receive),
+            TimePoint::from_millis(server_send),
+            TimePoint::from_millis(client_receive),
+        );
+    }
+
+    #[test]
+    fn offset_of_symmetric_delays_is_exact() {
+        let mut clock_sync = ClockSync::default();
+        add_sample(&mut clock_sync, 500, 10, 10);
+        assert_eq!(clock_sync.clock().offset_millis(), 500);
+        assert_eq!(clock_sync.rtt(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn sample_with_the_lowest_rtt_wins() {
+        let mut clock_sync = ClockSync::default();
+        add_sample(&mut clock_sync, 500, 10, 10);
+        // Asymmetric delays skew the offset, but the sample is slower
+        add_sample(&mut clock_sync, 500, 100, 20);
+        assert_eq!(clock_sync.clock().offset_millis(), 500);
+
+        add_sample(&mut clock_sync, 300, 2, 2);
+        assert_eq!(clock_sync.clock().offset_millis(), 300);
+        assert_eq!(clock_sync.rtt(), Some(Duration::from_millis(4)));
+    }
+
+    #[test]
+    fn oldest_samples_are_dropped() {
+        let mut clock_sync = ClockSync::default();
+        add_sample(&mut clock_sync, 500, 2, 2);
+        for _ in 0..MAX_SAMPLES {
+            add_sample(&mut clock_sync, 300, 10, 10);
+        }
+        assert_eq!(clock_sync.clock().offset_millis(), 300);
+    }
+
+    #[test]
+    fn pings_are_answered_and_pongs_update_the_estimate() {
+        let mut server = ClockSync::default();
+        let mut client = ClockSync::default();
+
+        let pong = server.handle(&client.ping()).unwrap();
+        assert!(matches!(pong, TimeSyncPacket::Pong { .. }));
+        assert!(client.handle(&pong).is_none());
+        assert!(client.rtt().is_some());
+    }
+}
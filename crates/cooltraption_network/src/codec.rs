@@ -0,0 +1,165 @@
+use std::fmt::{Display, Formatter};
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::packets::Packet;
+
+/// Bumped whenever the frame layout or the packet types change incompatibly
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Every frame starts with the protocol version and the id of the codec that encoded it
+const HEADER_LEN: usize = 2;
+
+#[derive(Debug)]
+pub enum CodecError {
+    TruncatedFrame,
+    UnsupportedVersion(u8),
+    UnexpectedCodec { expected: u8, found: u8 },
+    Json(serde_json::Error),
+    Bincode(bincode::Error),
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::TruncatedFrame => write!(f, "frame is shorter than its header"),
+            CodecError::UnsupportedVersion(version) => write!(
+                f,
+                "protocol version {} is not supported, expected {}",
+                version, PROTOCOL_VERSION
+            ),
+            CodecError::UnexpectedCodec { expected, found } => {
+                write!(
+                    f,
+                    "frame was encoded by codec {}, expected {}",
+                    found, expected
+                )
+            }
+            CodecError::Json(error) => write!(f, "json: {}", error),
+            CodecError::Bincode(error) => write!(f, "bincode: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+pub trait Codec<T>: Send + Sync {
+    fn id(&self) -> u8;
+    fn encode_payload(&self, packet: &Packet<T>) -> Result<Vec<u8>, CodecError>;
+    fn decode_payload(&self, payload: &[u8]) -> Result<Packet<T>, CodecError>;
+
+    fn encode(&self, packet: &Packet<T>) -> Result<Vec<u8>, CodecError> {
+        let payload = self.encode_payload(packet)?;
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.push(PROTOCOL_VERSION);
+        frame.push(self.id());
+        frame.extend(payload);
+        Ok(frame)
+    }
+
+    fn decode(&self, frame: &[u8]) -> Result<Packet<T>, CodecError> {
+        if frame.len() < HEADER_LEN {
+            return Err(CodecError::TruncatedFrame);
+        }
+        let (version, codec_id) = (frame[0], frame[1]);
+        if version != PROTOCOL_VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+        if codec_id != self.id() {
+            return Err(CodecError::UnexpectedCodec {
+                expected: self.id(),
+                found: codec_id,
+            });
+        }
+        self.decode_payload(&frame[HEADER_LEN..])
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn encode_payload(&self, packet: &Packet<T>) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(packet).map_err(CodecError::Json)
+    }
+
+    fn decode_payload(&self, payload: &[u8]) -> Result<Packet<T>, CodecError> {
+        serde_json::from_slice(payload).map_err(CodecError::Json)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BincodeCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for BincodeCodec {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn encode_payload(&self, packet: &Packet<T>) -> Result<Vec<u8>, CodecError> {
+        bincode::DefaultOptions::new()
+            .serialize(packet)
+            .map_err(CodecError::Bincode)
+    }
+
+    fn decode_payload(&self, payload: &[u8]) -> Result<Packet<T>, CodecError> {
+        bincode::DefaultOptions::new()
+            .deserialize(payload)
+            .map_err(CodecError::Bincode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ChatMessage;
+
+    fn chat_message() -> Packet<u32> {
+        Packet::ChatMessage(ChatMessage(String::from("hello")))
+    }
+
+    #[test]
+    fn frames_round_trip_with_every_codec() {
+        let codecs: [&dyn Codec<u32>; 2] = [&JsonCodec, &BincodeCodec];
+        for codec in codecs {
+            let frame = codec.encode(&Packet::ClientPacket(7)).unwrap();
+            assert!(matches!(codec.decode(&frame), Ok(Packet::ClientPacket(7))));
+        }
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        let frame = Codec::<u32>::encode(&BincodeCodec, &chat_message()).unwrap();
+
+        assert!(matches!(
+            Codec::<u32>::decode(&BincodeCodec, &frame[..1]),
+            Err(CodecError::TruncatedFrame)
+        ));
+
+        let mut outdated = frame.clone();
+        outdated[0] = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            Codec::<u32>::decode(&BincodeCodec, &outdated),
+            Err(CodecError::UnsupportedVersion(version)) if version == PROTOCOL_VERSION + 1
+        ));
+
+        assert!(matches!(
+            Codec::<u32>::decode(&JsonCodec, &frame),
+            Err(CodecError::UnexpectedCodec {
+                expected: 0,
+                found: 1
+            })
+        ));
+
+        let truncated_payload = &frame[..frame.len() - 1];
+        assert!(matches!(
+            Codec::<u32>::decode(&BincodeCodec, truncated_payload),
+            Err(CodecError::Bincode(_))
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Extra delay of messages that are held back to arrive after messages sent later
+const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+/// Artificially degrades a connection for testing, applied to outbound and inbound messages
+/// separately. Probabilities range from 0 to 1.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NetworkConditions {
+    pub latency: Duration,
+    /// Upper bound of the random delay added on top of the latency
+    pub jitter: Duration,
+    pub loss: f64,
+    pub reordering: f64,
+    pub duplication: f64,
+    /// The same seed produces the same sequence of delays and losses
+    pub seed: u64,
+}
+
+/// Decides the fate of every message according to the `NetworkConditions`
+#[derive(Debug, Clone)]
+pub(crate) struct ConditionSimulator {
+    conditions: NetworkConditions,
+    rng: Arc<SplitMix64>,
+}
+
+impl ConditionSimulator {
+    pub fn new(conditions: NetworkConditions) -> Self {
+        Self {
+            rng: Arc::new(SplitMix64::new(conditions.seed)),
+            conditions,
+        }
+    }
+
+    /// Delay of every copy of a message that gets delivered, empty if the message is lost
+    pub fn delays(&self) -> Vec<Duration> {
+        if self.rng.chance(self.conditions.loss) {
+            return vec![];
+        }
+        let copies = if self.rng.chance(self.conditions.duplication) {
+            2
+        } else {
+            1
+        };
+        (0..copies).map(|_| self.delay()).collect()
+    }
+
+    fn delay(&self) -> Duration {
+        let jitter = self.conditions.jitter.mul_f64(self.rng.next_f64());
+        let reorder_delay = if self.rng.chance(self.conditions.reordering) {
+            REORDER_DELAY
+        } else {
+            Duration::ZERO
+        };
+        self.conditions.latency + jitter + reorder_delay
+    }
+}
+
+/// Small seedable generator, shared between the threads sending messages
+#[derive(Debug)]
+struct SplitMix64 {
+    state: AtomicU64,
+}
+
+impl SplitMix64 {
+    const GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+    fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(Self::GAMMA, Ordering::Relaxed)
+            .wrapping_add(Self::GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in `[0, 1)`
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lossy() -> NetworkConditions {
+        NetworkConditions {
+            latency: Duration::from_millis(40),
+            jitter: Duration::from_millis(20),
+            loss: 0.25,
+            reordering: 0.1,
+            duplication: 0.1,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn same_seed_produces_the_same_fates() {
+        let fates = |conditions| {
+            let simulator = ConditionSimulator::new(conditions);
+            (0..100).map(|_| simulator.delays()).collect::<Vec<_>>()
+        };
+        assert_eq!(fates(lossy()), fates(lossy()));
+        assert_ne!(
+            fates(lossy()),
+            fates(NetworkConditions { seed: 8, ..lossy() })
+        );
+    }
+
+    #[test]
+    fn delays_stay_within_latency_jitter_and_reordering() {
+        let conditions = lossy();
+        let simulator = ConditionSimulator::new(conditions);
+        let max_delay = conditions.latency + conditions.jitter + REORDER_DELAY;
+        let mut lost = 0;
+        for _ in 0..10_000 {
+            let delays = simulator.delays();
+            if delays.is_empty() {
+                lost += 1;
+            }
+            assert!(delays.len() <= 2);
+            assert!(delays
+                .iter()
+                .all(|delay| (conditions.latency..=max_delay).contains(delay)));
+        }
+        assert!((2_000..3_000).contains(&lost), "{} messages lost", lost);
+    }
+
+    #[test]
+    fn perfect_conditions_deliver_every_message_once() {
+        let latency = Duration::from_millis(30);
+        let simulator = ConditionSimulator::new(NetworkConditions {
+            latency,
+            ..Default::default()
+        });
+        for _ in 0..100 {
+            assert_eq!(simulator.delays(), vec![latency]);
+        }
+    }
+
+    #[test]
+    fn certain_loss_and_duplication_are_applied_to_every_message() {
+        let lost = ConditionSimulator::new(NetworkConditions {
+            loss: 1.0,
+            ..Default::default()
+        });
+        let duplicated = ConditionSimulator::new(NetworkConditions {
+            duplication: 1.0,
+            ..Default::default()
+        });
+        for _ in 0..100 {
+            assert!(lost.delays().is_empty());
+            assert_eq!(duplicated.delays().len(), 2);
+        }
+    }
+}
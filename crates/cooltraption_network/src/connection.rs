@@ -19,6 +19,10 @@ impl Connection {
             socket_addr,
         }
     }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.socket_addr
+    }
 }
 
 #[allow(dead_code)]
@@ -1,11 +1,13 @@
 use std::sync::MutexGuard;
 
+use serde::{de::DeserializeOwned, Serialize};
+
 use crate::{
     builder::NodeEventHandlerBuilder,
     network_state::{NetworkStateEvent, NetworkStateImpl, NodeEventHandler},
 };
 
-pub fn networker<T>() -> NodeEventHandler<T> {
+pub fn networker<T: Serialize + DeserializeOwned + 'static>() -> NodeEventHandler<T> {
     let mut builder = NodeEventHandlerBuilder::default();
     let handler = |_event: &NetworkStateEvent<T>, _locked_state: &mut MutexGuard<NetworkStateImpl<T>>| {};
 
@@ -1,6 +1,13 @@
 pub mod builder;
 pub mod client;
+pub mod clock_sync;
+pub mod codec;
+pub mod conditions;
 pub mod connection;
 pub mod director;
 pub mod network_state;
 pub mod packets;
+pub mod reliability;
+pub mod session;
+pub mod stats;
+pub mod transport;
@@ -1,45 +1,172 @@
-use std::{
-    marker::PhantomData,
-    sync::{Arc, Mutex, MutexGuard},
-};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
+use crate::client::ReconnectPolicy;
+use crate::clock_sync::{ClockSync, TimeSyncPacket, CLOCK_SYNC_INTERVAL};
+use crate::codec::{Codec, CodecError};
+use crate::conditions::{ConditionSimulator, NetworkConditions};
 use crate::connection::Connection;
 use crate::packets::Packet;
+use crate::reliability::{Channel, ReliableEndpoint, RESEND_INTERVAL};
+use crate::stats::{NetworkStats, TrafficCounter};
+use crate::transport::Transport;
 use bimap::BiMap;
 
+use cooltraption_common::types::SyncedClock;
+use log::{error, info, warn};
 use message_io::{
-    network::Endpoint,
+    network::{Endpoint, NetEvent, ResourceId, SendStatus},
     node::{NodeEvent, NodeHandler, NodeListener},
 };
-use serde::{de::DeserializeOwned, Serialize};
 
-pub enum Signal {}
+pub enum Signal {
+    ClockSyncPing,
+    Reconnect,
+    /// Frame held back by the `ConditionSimulator` that is now sent
+    DeliverOutbound(Endpoint, Vec<u8>),
+    /// Frame held back by the `ConditionSimulator` that is now received
+    DeliverInbound(Endpoint, Vec<u8>),
+    /// Resends unacknowledged datagrams and drops datagram peers that timed out
+    Resend,
+}
+
+#[derive(Clone)]
+struct Reconnector {
+    server: SocketAddr,
+    transport: Transport,
+    policy: ReconnectPolicy,
+    failed_attempts: u32,
+}
 
 #[derive(Clone)]
 pub struct NetworkStateImpl<T> {
     connections: BiMap<Connection, Endpoint>,
     node_handler: NodeHandler<Signal>,
-    _phantom: PhantomData<T>,
+    codec: Arc<dyn Codec<T>>,
+    clock_sync: ClockSync,
+    reconnector: Option<Reconnector>,
+    condition_simulator: Option<ConditionSimulator>,
+    /// Resources of unreliable transports, their endpoints are made reliable per `Channel`
+    datagram_resources: HashSet<ResourceId>,
+    /// Mutated while sending, which only requires a shared reference
+    reliable_endpoints: RefCell<HashMap<Endpoint, ReliableEndpoint>>,
+    resending: bool,
+    traffic: RefCell<HashMap<Endpoint, TrafficCounter>>,
 }
 
 impl<T> NetworkStateImpl<T> {
-    pub fn new(node_handler: NodeHandler<Signal>) -> Self {
+    pub fn new(node_handler: NodeHandler<Signal>, codec: Arc<dyn Codec<T>>) -> Self {
         Self {
             connections: Default::default(),
             node_handler,
-            _phantom: PhantomData,
+            codec,
+            clock_sync: Default::default(),
+            reconnector: None,
+            condition_simulator: None,
+            datagram_resources: Default::default(),
+            reliable_endpoints: Default::default(),
+            resending: false,
+            traffic: Default::default(),
+        }
+    }
+
+    pub fn connect(
+        &mut self,
+        server: SocketAddr,
+        transport: Transport,
+        reconnect_policy: ReconnectPolicy,
+    ) {
+        self.reconnector = Some(Reconnector {
+            server,
+            transport,
+            policy: reconnect_policy,
+            failed_attempts: 0,
+        });
+        self.try_connect();
+    }
+
+    /// Starts listening and returns the address that was bound, which tells the actual port if
+    /// port 0 was requested
+    pub fn listen(
+        &mut self,
+        addr: impl ToSocketAddrs,
+        transport: Transport,
+    ) -> io::Result<SocketAddr> {
+        let (resource_id, local_addr) =
+            self.node_handler.network().listen(transport.into(), addr)?;
+        if !transport.is_reliable() {
+            self.add_datagram_resource(resource_id);
         }
+        Ok(local_addr)
+    }
+
+    /// Sends the packet on its `Packet::default_channel`
+    pub fn send_packet(&self, packet: Packet<T>, connection: &Connection) {
+        let channel = packet.default_channel();
+        self.send_packet_on(packet, connection, channel);
+    }
+
+    /// Packets that cannot be encoded or are addressed to an unknown connection are dropped.
+    /// The channel only makes a difference on unreliable transports.
+    pub fn send_packet_on(&self, packet: Packet<T>, connection: &Connection, channel: Channel) {
+        let Some(endpoint) = self.connections.get_by_left(connection) else {
+            warn!("Dropping packet for unknown connection {:?}", connection);
+            return;
+        };
+        match self.codec.encode(&packet) {
+            Ok(frame) => self.send_frame(*endpoint, channel, frame),
+            Err(e) => error!("Could not encode packet for {:?}: {}", connection, e),
+        }
+    }
+
+    pub fn set_codec(&mut self, codec: Arc<dyn Codec<T>>) {
+        self.codec = codec;
+    }
+
+    /// Simulates latency, jitter and loss on every message, `None` restores the real network
+    pub fn set_network_conditions(&mut self, network_conditions: Option<NetworkConditions>) {
+        self.condition_simulator = network_conditions.map(ConditionSimulator::new);
+    }
+
+    /// Clock of the server as estimated by the client, equal to the local clock on the server
+    pub fn clock(&self) -> SyncedClock {
+        self.clock_sync.clock()
     }
 
-    pub fn send_packet(&self, packet: Packet<T>, connection: &Connection)
-    where
-        T: Serialize,
-    {
-        let endpoint = self.connections.get_by_left(connection).unwrap();
-        self.node_handler.network().send(
-            *endpoint,
-            serde_json::to_string(&packet).unwrap().as_bytes(),
-        );
+    pub fn rtt(&self) -> Option<Duration> {
+        self.clock_sync.rtt()
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        let traffic = self.traffic.borrow();
+        let reliable_endpoints = self.reliable_endpoints.borrow();
+        let connections = self
+            .connections
+            .iter()
+            .map(|(connection, endpoint)| {
+                let mut stats = traffic
+                    .get(endpoint)
+                    .map(TrafficCounter::stats)
+                    .unwrap_or_default();
+                // Only the connecting side pings, so only its clock estimate has samples
+                if self
+                    .reconnector
+                    .as_ref()
+                    .is_some_and(|reconnector| reconnector.server == endpoint.addr())
+                {
+                    stats.rtt = self.rtt();
+                }
+                if let Some(reliable_endpoint) = reliable_endpoints.get(endpoint) {
+                    stats.loss = reliable_endpoint.loss();
+                }
+                (connection.clone(), stats)
+            })
+            .collect();
+        NetworkStats { connections }
     }
 
     pub fn connections(&self) -> Vec<&Connection> {
@@ -47,9 +174,12 @@ impl<T> NetworkStateImpl<T> {
     }
 
     pub fn disconnect(&mut self, id: Connection) {
-        let resource_id = self.connections.get_by_left(&id).unwrap().resource_id();
-        self.node_handler.network().remove(resource_id);
-        self.connections.remove_by_left(&id);
+        let endpoint = *self.connections.get_by_left(&id).unwrap();
+        // Endpoints of a datagram listener share the resource of the listener
+        if !self.datagram_resources.contains(&endpoint.resource_id()) {
+            self.node_handler.network().remove(endpoint.resource_id());
+        }
+        self.remove_endpoint(&endpoint);
     }
 
     pub fn stop_listener(&mut self) {
@@ -63,43 +193,305 @@ impl<T> NetworkStateImpl<T> {
 
     fn remove_endpoint(&mut self, endpoint: &Endpoint) {
         self.connections.remove_by_right(endpoint);
+        self.reliable_endpoints.get_mut().remove(endpoint);
+        self.traffic.get_mut().remove(endpoint);
     }
 
-    fn apply_node_event(&mut self, message: &NodeEvent<'_, Signal>) -> NetworkStateEvent<T>
-    where
-        T: DeserializeOwned,
-    {
-        if let NodeEvent::Network(net_event) = message {
-            let network_state_event: NetworkStateEvent<T> = match net_event {
-                message_io::network::NetEvent::Connected(endpoint, established) => {
-                    if !established {
-                        panic!("connection failed");
-                    }
-                    self.add_endpoint(*endpoint);
-                    NetworkStateEvent::Connected(
-                        self.connections.get_by_right(endpoint).unwrap().clone(),
-                    )
+    fn add_datagram_resource(&mut self, resource_id: ResourceId) {
+        self.datagram_resources.insert(resource_id);
+        if !std::mem::replace(&mut self.resending, true) {
+            self.node_handler
+                .signals()
+                .send_with_timer(Signal::Resend, RESEND_INTERVAL);
+        }
+    }
+
+    fn try_connect(&mut self) {
+        let Some(reconnector) = &self.reconnector else {
+            return;
+        };
+        let (server, transport) = (reconnector.server, reconnector.transport);
+        match self
+            .node_handler
+            .network()
+            .connect(transport.into(), server)
+        {
+            Ok((endpoint, _)) => {
+                if !transport.is_reliable() {
+                    self.add_datagram_resource(endpoint.resource_id());
+                }
+            }
+            Err(e) => {
+                warn!("Could not connect to {}: {}", server, e);
+                self.schedule_reconnect();
+            }
+        }
+    }
+
+    fn schedule_reconnect(&mut self) {
+        let Some(reconnector) = &mut self.reconnector else {
+            return;
+        };
+        match reconnector.policy.delay_for(reconnector.failed_attempts) {
+            Some(delay) => {
+                reconnector.failed_attempts += 1;
+                info!("Reconnecting to {} in {:?}", reconnector.server, delay);
+                self.node_handler
+                    .signals()
+                    .send_with_timer(Signal::Reconnect, delay);
+            }
+            None => error!("Giving up on connecting to {}", reconnector.server),
+        }
+    }
+
+    fn send_frame(&self, endpoint: Endpoint, channel: Channel, frame: Vec<u8>) {
+        if !self.datagram_resources.contains(&endpoint.resource_id()) {
+            self.transmit(endpoint, frame);
+            return;
+        }
+        let datagram = self
+            .reliable_endpoints
+            .borrow_mut()
+            .entry(endpoint)
+            .or_insert_with(ReliableEndpoint::new)
+            .wrap(channel, &frame);
+        match datagram {
+            Some(datagram) => self.transmit(endpoint, datagram),
+            None => error!(
+                "Dropping frame of {} bytes for {}, it exceeds the maximum datagram size",
+                frame.len(),
+                endpoint.addr()
+            ),
+        }
+    }
+
+    /// Sends the bytes as they are, unless the `ConditionSimulator` holds them back or drops them
+    fn transmit(&self, endpoint: Endpoint, bytes: Vec<u8>) {
+        self.traffic
+            .borrow_mut()
+            .entry(endpoint)
+            .or_insert_with(TrafficCounter::new)
+            .record_out(bytes.len());
+        let Some(condition_simulator) = &self.condition_simulator else {
+            self.send_now(endpoint, &bytes);
+            return;
+        };
+        for delay in condition_simulator.delays() {
+            self.node_handler
+                .signals()
+                .send_with_timer(Signal::DeliverOutbound(endpoint, bytes.clone()), delay);
+        }
+    }
+
+    /// Reliable datagrams that could not be sent stay unacknowledged and are resent
+    fn send_now(&self, endpoint: Endpoint, bytes: &[u8]) {
+        match self.node_handler.network().send(endpoint, bytes) {
+            SendStatus::Sent => {}
+            SendStatus::MaxPacketSizeExceeded => error!(
+                "Dropping {} bytes for {}, they exceed the maximum packet size",
+                bytes.len(),
+                endpoint.addr()
+            ),
+            SendStatus::ResourceNotFound => {
+                warn!(
+                    "Dropping packet for {}, it is not connected",
+                    endpoint.addr()
+                )
+            }
+            SendStatus::ResourceNotAvailable => {
+                warn!(
+                    "Dropping packet for {}, it is not ready yet",
+                    endpoint.addr()
+                )
+            }
+        }
+    }
+
+    fn resend_unacked(&mut self) -> Vec<NetworkStateEvent<T>> {
+        for (endpoint, reliable_endpoint) in self.reliable_endpoints.borrow().iter() {
+            for datagram in reliable_endpoint.unacked() {
+                self.transmit(*endpoint, datagram.clone());
+            }
+        }
+
+        let timed_out: Vec<Endpoint> = self
+            .reliable_endpoints
+            .get_mut()
+            .iter()
+            .filter(|(_, reliable_endpoint)| reliable_endpoint.timed_out())
+            .map(|(endpoint, _)| *endpoint)
+            .collect();
+        timed_out
+            .iter()
+            .filter_map(|endpoint| {
+                warn!("{} timed out", endpoint.addr());
+                self.handle_disconnect(endpoint)
+            })
+            .collect()
+    }
+
+    fn send_ping(&self) {
+        let ping = self.clock_sync.ping();
+        for connection in self.connections() {
+            self.send_packet(Packet::TimeSync(ping), connection);
+        }
+    }
+
+    fn apply_node_event(&mut self, message: &NodeEvent<'_, Signal>) -> Vec<NetworkStateEvent<T>> {
+        let net_event = match message {
+            NodeEvent::Network(net_event) => net_event,
+            NodeEvent::Signal(Signal::ClockSyncPing) => {
+                self.send_ping();
+                self.node_handler
+                    .signals()
+                    .send_with_timer(Signal::ClockSyncPing, CLOCK_SYNC_INTERVAL);
+                return vec![];
+            }
+            NodeEvent::Signal(Signal::Reconnect) => {
+                self.try_connect();
+                return vec![];
+            }
+            NodeEvent::Signal(Signal::DeliverOutbound(endpoint, bytes)) => {
+                self.send_now(*endpoint, bytes);
+                return vec![];
+            }
+            NodeEvent::Signal(Signal::DeliverInbound(endpoint, bytes)) => {
+                return self.receive(endpoint, bytes);
+            }
+            NodeEvent::Signal(Signal::Resend) => {
+                self.node_handler
+                    .signals()
+                    .send_with_timer(Signal::Resend, RESEND_INTERVAL);
+                return self.resend_unacked();
+            }
+        };
+        let network_state_event: NetworkStateEvent<T> = match net_event {
+            NetEvent::Connected(endpoint, established) => {
+                if !established {
+                    warn!("Connection to {} failed", endpoint.addr());
+                    self.schedule_reconnect();
+                    return vec![NetworkStateEvent::ConnectionFailed(endpoint.addr())];
                 }
-                message_io::network::NetEvent::Accepted(endpoint, _) => {
-                    self.add_endpoint(*endpoint);
-                    NetworkStateEvent::Accepted(
-                        self.connections.get_by_right(endpoint).unwrap().clone(),
-                    )
+                if let Some(reconnector) = &mut self.reconnector {
+                    reconnector.failed_attempts = 0;
                 }
-                message_io::network::NetEvent::Message(endpoint, message) => {
-                    let connection = self.connections.get_by_right(endpoint).unwrap().clone();
-                    let packet = serde_json::from_slice::<Packet<T>>(message).unwrap();
-                    NetworkStateEvent::Message(connection, packet)
+                self.add_endpoint(*endpoint);
+                if self.datagram_resources.contains(&endpoint.resource_id()) {
+                    self.reliable_endpoints
+                        .get_mut()
+                        .insert(*endpoint, ReliableEndpoint::new());
                 }
-                message_io::network::NetEvent::Disconnected(endpoint) => {
-                    let connection = self.connections.get_by_right(endpoint).unwrap().clone();
-                    self.remove_endpoint(endpoint);
-                    NetworkStateEvent::Disconnected(connection)
+                if self.clock_sync.start_pinging() {
+                    self.node_handler.signals().send(Signal::ClockSyncPing);
                 }
-            };
-            network_state_event
-        } else {
-            unimplemented!();
+                NetworkStateEvent::Connected(
+                    self.connections.get_by_right(endpoint).unwrap().clone(),
+                )
+            }
+            NetEvent::Accepted(endpoint, _) => {
+                self.add_endpoint(*endpoint);
+                NetworkStateEvent::Accepted(
+                    self.connections.get_by_right(endpoint).unwrap().clone(),
+                )
+            }
+            NetEvent::Message(endpoint, message) => {
+                let Some(condition_simulator) = &self.condition_simulator else {
+                    return self.receive(endpoint, message);
+                };
+                for delay in condition_simulator.delays() {
+                    self.node_handler.signals().send_with_timer(
+                        Signal::DeliverInbound(*endpoint, message.to_vec()),
+                        delay,
+                    );
+                }
+                return vec![];
+            }
+            NetEvent::Disconnected(endpoint) => {
+                return self.handle_disconnect(endpoint).into_iter().collect();
+            }
+        };
+        vec![network_state_event]
+    }
+
+    fn handle_disconnect(&mut self, endpoint: &Endpoint) -> Option<NetworkStateEvent<T>> {
+        let connection = self.connections.get_by_right(endpoint)?.clone();
+        self.remove_endpoint(endpoint);
+        if self
+            .reconnector
+            .as_ref()
+            .is_some_and(|reconnector| reconnector.server == endpoint.addr())
+        {
+            // Datagram resources of a client are not closed by the remote side
+            if self.datagram_resources.remove(&endpoint.resource_id()) {
+                self.node_handler.network().remove(endpoint.resource_id());
+            }
+            self.schedule_reconnect();
+        }
+        Some(NetworkStateEvent::Disconnected(connection))
+    }
+
+    /// Unwraps datagrams into frames, datagram transports have no handshake so the first valid
+    /// datagram of an unknown peer accepts it
+    fn receive(&mut self, endpoint: &Endpoint, bytes: &[u8]) -> Vec<NetworkStateEvent<T>> {
+        self.traffic
+            .get_mut()
+            .entry(*endpoint)
+            .or_insert_with(TrafficCounter::new)
+            .record_in(bytes.len());
+        if !self.datagram_resources.contains(&endpoint.resource_id()) {
+            return self.receive_frame(endpoint, bytes).into_iter().collect();
+        }
+
+        let is_new = !self.connections.contains_right(endpoint);
+        let received = self
+            .reliable_endpoints
+            .get_mut()
+            .entry(*endpoint)
+            .or_insert_with(ReliableEndpoint::new)
+            .receive(bytes);
+        let Some(received) = received else {
+            warn!("Received malformed datagram from {}", endpoint.addr());
+            if is_new {
+                self.reliable_endpoints.get_mut().remove(endpoint);
+                self.traffic.get_mut().remove(endpoint);
+            }
+            return vec![];
+        };
+
+        let mut events = vec![];
+        if is_new {
+            self.add_endpoint(*endpoint);
+            let connection = self.connections.get_by_right(endpoint).unwrap().clone();
+            events.push(NetworkStateEvent::Accepted(connection));
+        }
+        if let Some(ack) = received.ack {
+            self.transmit(*endpoint, ack);
+        }
+        for frame in received.frames {
+            events.extend(self.receive_frame(endpoint, &frame));
+        }
+        events
+    }
+
+    /// Held back frames may arrive after their connection was closed, those are dropped
+    fn receive_frame(&mut self, endpoint: &Endpoint, frame: &[u8]) -> Option<NetworkStateEvent<T>> {
+        let connection = self.connections.get_by_right(endpoint)?.clone();
+        match self.codec.decode(frame) {
+            Ok(Packet::TimeSync(time_sync_packet)) => {
+                self.handle_time_sync(&time_sync_packet, &connection);
+                None
+            }
+            Ok(packet) => Some(NetworkStateEvent::Message(connection, packet)),
+            Err(e) => {
+                warn!("Received malformed packet from {:?}: {}", connection, e);
+                Some(NetworkStateEvent::InvalidMessage(connection, e))
+            }
+        }
+    }
+
+    fn handle_time_sync(&mut self, time_sync_packet: &TimeSyncPacket, connection: &Connection) {
+        if let Some(reply) = self.clock_sync.handle(time_sync_packet) {
+            self.send_packet(Packet::TimeSync(reply), connection);
         }
     }
 }
@@ -111,7 +503,11 @@ pub enum NetworkStateEvent<T> {
     Connected(Connection),
     Accepted(Connection),
     Disconnected(Connection),
+    /// Connecting to the server failed, it is retried according to the `ReconnectPolicy`
+    ConnectionFailed(SocketAddr),
     Message(Connection, Packet<T>),
+    /// A message arrived that the configured codec could not decode
+    InvalidMessage(Connection, CodecError),
 }
 
 pub struct NodeEventHandler<T> {
@@ -133,16 +529,14 @@ impl<T> NodeEventHandler<T> {
         }
     }
 
-    pub fn handle_event_loop(mut self)
-    where
-        T: DeserializeOwned,
-    {
+    pub fn handle_event_loop(mut self) {
         self.node_listener
             .for_each(move |event: NodeEvent<'_, Signal>| {
                 let mut network_state_lock = self.network_state.lock().unwrap();
-                let network_state_event = network_state_lock.apply_node_event(&event);
-                for f in self.network_state_publisher.iter_mut() {
-                    f(&network_state_event, &mut network_state_lock);
+                for network_state_event in network_state_lock.apply_node_event(&event) {
+                    for f in self.network_state_publisher.iter_mut() {
+                        f(&network_state_event, &mut network_state_lock);
+                    }
                 }
             });
     }
@@ -1,9 +1,27 @@
 use serde::{Deserialize, Serialize};
 
+use crate::clock_sync::TimeSyncPacket;
+use crate::reliability::Channel;
+use crate::session::LobbyPacket;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Packet<T> {
     ChatMessage(ChatMessage),
     ClientPacket(T),
+    /// Handled by the network state itself and never published to the event handlers
+    TimeSync(TimeSyncPacket),
+    Lobby(LobbyPacket),
+}
+
+impl<T> Packet<T> {
+    /// Channel used by `NetworkStateImpl::send_packet`
+    pub fn default_channel(&self) -> Channel {
+        match self {
+            // Outdated clock samples are worthless and resending them would distort the rtt
+            Packet::TimeSync(_) => Channel::UnreliableSequenced,
+            _ => Channel::ReliableOrdered,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
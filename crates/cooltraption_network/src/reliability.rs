@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+/// Interval in which datagrams that were not acknowledged yet are sent again
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Datagram peers that were not heard of for this long are considered disconnected
+pub const DATAGRAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest payload a UDP datagram can carry over IPv4
+pub const MAX_DATAGRAM_LEN: usize = 65_507;
+
+/// Reliable frames this many sequence numbers ahead of the next expected one are dropped
+const RECEIVE_WINDOW: u32 = 1024;
+
+const HEADER_LEN: usize = 6;
+const PAYLOAD: u8 = 0;
+const ACK: u8 = 1;
+
+/// Delivery guarantees of a packet on unreliable transports, reliable transports deliver every
+/// packet in order regardless of the channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    ReliableOrdered,
+    /// Delivered exactly once, but possibly after packets that were sent later
+    ReliableUnordered,
+    /// Lost packets are not resent and packets older than the newest received one are dropped
+    UnreliableSequenced,
+}
+
+impl Channel {
+    pub fn is_reliable(&self) -> bool {
+        !matches!(self, Channel::UnreliableSequenced)
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Channel::ReliableOrdered => 0,
+            Channel::ReliableUnordered => 1,
+            Channel::UnreliableSequenced => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Channel::ReliableOrdered),
+            1 => Some(Channel::ReliableUnordered),
+            2 => Some(Channel::UnreliableSequenced),
+            _ => None,
+        }
+    }
+}
+
+/// Datagrams start with their kind, the channel and a big endian sequence number
+fn datagram(kind: u8, channel: Channel, sequence: u32, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(HEADER_LEN + payload.len());
+    datagram.push(kind);
+    datagram.push(channel.to_byte());
+    datagram.extend_from_slice(&sequence.to_be_bytes());
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+/// Serial number comparison, `a` is before `b` if it is less than half the number space behind
+fn is_before(a: u32, b: u32) -> bool {
+    a != b && b.wrapping_sub(a) < 1 << 31
+}
+
+/// What a received datagram resulted in
+#[derive(Default)]
+pub(crate) struct Received {
+    /// Frames that are ready to be decoded, in the order they have to be handled
+    pub frames: Vec<Vec<u8>>,
+    /// Acknowledgement that has to be sent back to the peer
+    pub ack: Option<Vec<u8>>,
+}
+
+/// Channel bookkeeping of a single peer of an unreliable transport
+#[derive(Debug, Clone)]
+pub(crate) struct ReliableEndpoint {
+    next_sequence: BTreeMap<Channel, u32>,
+    unacked: BTreeMap<(Channel, u32), Vec<u8>>,
+    /// Every sequence number below is known to be received
+    unordered_watermark: u32,
+    unordered_received: BTreeSet<u32>,
+    next_ordered: u32,
+    ordered_pending: BTreeMap<u32, Vec<u8>>,
+    newest_sequenced: Option<u32>,
+    /// Datagrams the peer sent on the unreliable channel up to the newest received one
+    sequenced_sent: u64,
+    sequenced_received: u64,
+    last_received: Instant,
+}
+
+impl ReliableEndpoint {
+    pub fn new() -> Self {
+        Self {
+            next_sequence: Default::default(),
+            unacked: Default::default(),
+            unordered_watermark: 0,
+            unordered_received: Default::default(),
+            next_ordered: 0,
+            ordered_pending: Default::default(),
+            newest_sequenced: None,
+            sequenced_sent: 0,
+            sequenced_received: 0,
+            last_received: Instant::now(),
+        }
+    }
+
+    /// Wraps the frame into a datagram, reliable datagrams are kept until they are acknowledged.
+    /// Returns `None` without using up a sequence number if the datagram would be too large.
+    pub fn wrap(&mut self, channel: Channel, frame: &[u8]) -> Option<Vec<u8>> {
+        if HEADER_LEN + frame.len() > MAX_DATAGRAM_LEN {
+            return None;
+        }
+        let sequence = self.next_sequence.entry(channel).or_default();
+        let datagram = datagram(PAYLOAD, channel, *sequence, frame);
+        if channel.is_reliable() {
+            self.unacked.insert((channel, *sequence), datagram.clone());
+        }
+        *sequence = sequence.wrapping_add(1);
+        Some(datagram)
+    }
+
+    pub fn unacked(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.unacked.values()
+    }
+
+    /// Estimated from the gaps in the sequence numbers of the unreliable channel
+    pub fn loss(&self) -> f32 {
+        if self.sequenced_sent == 0 {
+            return 0.0;
+        }
+        let sent = self.sequenced_sent;
+        1.0 - self.sequenced_received.min(sent) as f32 / sent as f32
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.last_received.elapsed() > DATAGRAM_TIMEOUT
+    }
+
+    /// Returns `None` if the datagram is malformed
+    pub fn receive(&mut self, datagram: &[u8]) -> Option<Received> {
+        if datagram.len() < HEADER_LEN {
+            return None;
+        }
+        let channel = Channel::from_byte(datagram[1])?;
+        let sequence = u32::from_be_bytes(datagram[2..HEADER_LEN].try_into().ok()?);
+        let payload = &datagram[HEADER_LEN..];
+        self.last_received = Instant::now();
+
+        match datagram[0] {
+            PAYLOAD => Some(self.receive_payload(channel, sequence, payload)),
+            ACK => {
+                self.unacked.remove(&(channel, sequence));
+                Some(Received::default())
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_payload(&mut self, channel: Channel, sequence: u32, payload: &[u8]) -> Received {
+        let window_start = match channel {
+            Channel::ReliableOrdered => Some(self.next_ordered),
+            Channel::ReliableUnordered => Some(self.unordered_watermark),
+            Channel::UnreliableSequenced => None,
+        };
+        // Not acknowledged either, so the peer resends it once the window moved on
+        if window_start.is_some_and(|start| {
+            !is_before(sequence, start) && sequence.wrapping_sub(start) >= RECEIVE_WINDOW
+        }) {
+            return Received::default();
+        }
+        let ack = channel
+            .is_reliable()
+            .then(|| datagram(ACK, channel, sequence, &[]));
+        let frames = match channel {
+            Channel::ReliableOrdered => {
+                if !is_before(sequence, self.next_ordered) {
+                    self.ordered_pending.insert(sequence, payload.to_vec());
+                }
+                let mut frames = vec![];
+                while let Some(frame) = self.ordered_pending.remove(&self.next_ordered) {
+                    frames.push(frame);
+                    self.next_ordered = self.next_ordered.wrapping_add(1);
+                }
+                frames
+            }
+            Channel::ReliableUnordered => {
+                if is_before(sequence, self.unordered_watermark)
+                    || !self.unordered_received.insert(sequence)
+                {
+                    vec![]
+                } else {
+                    while self.unordered_received.remove(&self.unordered_watermark) {
+                        self.unordered_watermark = self.unordered_watermark.wrapping_add(1);
+                    }
+                    vec![payload.to_vec()]
+                }
+            }
+            Channel::UnreliableSequenced => {
+                if self
+                    .newest_sequenced
+                    .is_some_and(|newest| !is_before(newest, sequence))
+                {
+                    vec![]
+                } else {
+                    self.sequenced_sent +=
+                        self.newest_sequenced.map_or(sequence as u64 + 1, |newest| {
+                            sequence.wrapping_sub(newest) as u64
+                        });
+                    self.newest_sequenced = Some(sequence);
+                    self.sequenced_received += 1;
+                    vec![payload.to_vec()]
+                }
+            }
+        };
+        Received { frames, ack }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Payloads of the frames the receiver handed out for the datagrams
+    fn deliver(receiver: &mut ReliableEndpoint, datagrams: &[&Vec<u8>]) -> Vec<Vec<u8>> {
+        datagrams
+            .iter()
+            .flat_map(|datagram| receiver.receive(datagram).unwrap().frames)
+            .collect()
+    }
+
+    fn wrap_all(sender: &mut ReliableEndpoint, channel: Channel, count: u8) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|frame| sender.wrap(channel, &[frame]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ordered_frames_are_delivered_once_in_order() {
+        let mut sender = ReliableEndpoint::new();
+        let mut receiver = ReliableEndpoint::new();
+        let datagrams = wrap_all(&mut sender, Channel::ReliableOrdered, 3);
+
+        let frames = deliver(
+            &mut receiver,
+            &[
+                &datagrams[2],
+                &datagrams[0],
+                &datagrams[2],
+                &datagrams[1],
+                &datagrams[0],
+            ],
+        );
+
+        assert_eq!(frames, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn unordered_frames_are_delivered_once_on_arrival() {
+        let mut sender = ReliableEndpoint::new();
+        let mut receiver = ReliableEndpoint::new();
+        let datagrams = wrap_all(&mut sender, Channel::ReliableUnordered, 3);
+
+        let frames = deliver(
+            &mut receiver,
+            &[
+                &datagrams[2],
+                &datagrams[0],
+                &datagrams[2],
+                &datagrams[1],
+                &datagrams[0],
+            ],
+        );
+
+        assert_eq!(frames, vec![vec![2], vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn sequenced_frames_older_than_the_newest_are_dropped() {
+        let mut sender = ReliableEndpoint::new();
+        let mut receiver = ReliableEndpoint::new();
+        let datagrams = wrap_all(&mut sender, Channel::UnreliableSequenced, 3);
+
+        let frames = deliver(
+            &mut receiver,
+            &[&datagrams[0], &datagrams[2], &datagrams[1], &datagrams[2]],
+        );
+
+        assert_eq!(frames, vec![vec![0], vec![2]]);
+        assert!(sender.unacked().next().is_none());
+    }
+
+    #[test]
+    fn acknowledged_datagrams_are_not_resent() {
+        let mut sender = ReliableEndpoint::new();
+        let mut receiver = ReliableEndpoint::new();
+        let datagrams = wrap_all(&mut sender, Channel::ReliableOrdered, 2);
+
+        let ack = receiver.receive(&datagrams[1]).unwrap().ack.unwrap();
+        sender.receive(&ack).unwrap();
+
+        assert_eq!(sender.unacked().collect::<Vec<_>>(), vec![&datagrams[0]]);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let mut receiver = ReliableEndpoint::new();
+        receiver.next_ordered = u32::MAX;
+        receiver.unordered_watermark = u32::MAX;
+        let channels = [Channel::ReliableOrdered, Channel::ReliableUnordered];
+        for channel in channels {
+            let mut sender = ReliableEndpoint::new();
+            sender.next_sequence.insert(channel, u32::MAX);
+            let datagrams = wrap_all(&mut sender, channel, 2);
+
+            let frames = deliver(
+                &mut receiver,
+                &[&datagrams[0], &datagrams[1], &datagrams[0]],
+            );
+
+            assert_eq!(frames, vec![vec![0], vec![1]]);
+        }
+    }
+
+    #[test]
+    fn reliable_frames_beyond_the_receive_window_are_dropped() {
+        let mut receiver = ReliableEndpoint::new();
+        for channel in [Channel::ReliableOrdered, Channel::ReliableUnordered] {
+            let beyond = datagram(PAYLOAD, channel, RECEIVE_WINDOW, &[0]);
+            let received = receiver.receive(&beyond).unwrap();
+            assert!(received.frames.is_empty());
+            assert!(received.ack.is_none());
+
+            let within = datagram(PAYLOAD, channel, RECEIVE_WINDOW - 1, &[0]);
+            assert!(receiver.receive(&within).unwrap().ack.is_some());
+        }
+        assert_eq!(receiver.ordered_pending.len(), 1);
+        assert_eq!(receiver.unordered_received.len(), 1);
+    }
+
+    #[test]
+    fn oversized_frames_are_not_wrapped() {
+        let mut sender = ReliableEndpoint::new();
+
+        assert!(sender
+            .wrap(Channel::ReliableOrdered, &vec![0; MAX_DATAGRAM_LEN])
+            .is_none());
+        assert!(sender.unacked().next().is_none());
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::{BTreeMap, HashMap};
+
+use cooltraption_common::types::PlayerId;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::connection::Connection;
+use crate::network_state::NetworkStateImpl;
+use crate::packets::Packet;
+
+/// Secret handed to a player on join, presenting it again resumes the player after a reconnect
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken(u128);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerInfo {
+    pub id: PlayerId,
+    pub name: String,
+    pub ready: bool,
+    pub connected: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomState {
+    pub name: String,
+    pub host: PlayerId,
+    pub players: Vec<PlayerInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LobbyPacket {
+    /// Sent by the client, a known `session_token` resumes the previous player and room
+    Join {
+        name: String,
+        room: String,
+        session_token: Option<SessionToken>,
+    },
+    Leave,
+    SetReady(bool),
+    /// Sent by the host, the match is only started if every connected player is ready
+    StartMatch,
+    Welcome {
+        player_id: PlayerId,
+        session_token: SessionToken,
+    },
+    RoomState(RoomState),
+    Rejected(String),
+}
+
+pub enum LobbyEvent {
+    Joined {
+        player_id: PlayerId,
+        room: String,
+        resumed: bool,
+    },
+    Disconnected {
+        player_id: PlayerId,
+        room: String,
+    },
+    Left {
+        player_id: PlayerId,
+        room: String,
+    },
+    /// The room was removed because none of its players are connected anymore
+    RoomClosed(String),
+    StartRequested(String),
+}
+
+struct Player {
+    name: String,
+    room: String,
+    session_token: SessionToken,
+    connection: Option<Connection>,
+    ready: bool,
+}
+
+/// Server side bookkeeping of players and the rooms they joined
+pub struct Lobby {
+    players: HashMap<PlayerId, Player>,
+    rooms: BTreeMap<String, Vec<PlayerId>>,
+    next_player_id: u64,
+    max_players_per_room: usize,
+}
+
+impl Lobby {
+    pub fn new(max_players_per_room: usize) -> Self {
+        Self {
+            players: Default::default(),
+            rooms: Default::default(),
+            next_player_id: 0,
+            max_players_per_room,
+        }
+    }
+
+    pub fn player_id(&self, connection: &Connection) -> Option<PlayerId> {
+        self.players
+            .iter()
+            .find(|(_, player)| player.connection.as_ref() == Some(connection))
+            .map(|(player_id, _)| *player_id)
+    }
+
+    pub fn connection(&self, player_id: PlayerId) -> Option<&Connection> {
+        self.players.get(&player_id)?.connection.as_ref()
+    }
+
+    pub fn room_of(&self, connection: &Connection) -> Option<&str> {
+        let player_id = self.player_id(connection)?;
+        Some(self.players[&player_id].room.as_str())
+    }
+
+    pub fn room_connections(&self, room: &str) -> Vec<Connection> {
+        self.rooms
+            .get(room)
+            .into_iter()
+            .flatten()
+            .filter_map(|player_id| self.players[player_id].connection.clone())
+            .collect()
+    }
+
+    pub fn room_size(&self, room: &str) -> usize {
+        self.rooms.get(room).map_or(0, Vec::len)
+    }
+
+    pub fn room_state(&self, room: &str) -> Option<RoomState> {
+        let player_ids = self.rooms.get(room)?;
+        Some(RoomState {
+            name: room.to_string(),
+            host: *player_ids.first()?,
+            players: player_ids
+                .iter()
+                .map(|player_id| {
+                    let player = &self.players[player_id];
+                    PlayerInfo {
+                        id: *player_id,
+                        name: player.name.clone(),
+                        ready: player.ready,
+                        connected: player.connection.is_some(),
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Clears the ready flags once a match started, so the next match needs a new ready-check
+    pub fn reset_ready<T>(&mut self, room: &str, network_state: &NetworkStateImpl<T>) {
+        for player_id in self.rooms.get(room).into_iter().flatten() {
+            if let Some(player) = self.players.get_mut(player_id) {
+                player.ready = false;
+            }
+        }
+        self.broadcast_room_state(room, network_state);
+    }
+
+    pub fn handle_packet<T>(
+        &mut self,
+        connection: &Connection,
+        packet: &LobbyPacket,
+        network_state: &NetworkStateImpl<T>,
+    ) -> Option<LobbyEvent> {
+        match packet {
+            LobbyPacket::Join {
+                name,
+                room,
+                session_token,
+            } => self.join(connection, name, room, *session_token, network_state),
+            LobbyPacket::Leave => self.leave(connection, network_state),
+            LobbyPacket::SetReady(ready) => {
+                let player_id = self.player_id(connection)?;
+                let player = self.players.get_mut(&player_id)?;
+                player.ready = *ready;
+                let room = player.room.clone();
+                self.broadcast_room_state(&room, network_state);
+                None
+            }
+            LobbyPacket::StartMatch => self.start_match(connection, network_state),
+            LobbyPacket::Welcome { .. } | LobbyPacket::RoomState(_) | LobbyPacket::Rejected(_) => {
+                warn!("{:?} sent a packet only the server may send", connection);
+                None
+            }
+        }
+    }
+
+    pub fn handle_disconnect<T>(
+        &mut self,
+        connection: &Connection,
+        network_state: &NetworkStateImpl<T>,
+    ) -> Option<LobbyEvent> {
+        let player_id = self.player_id(connection)?;
+        let player = self.players.get_mut(&player_id)?;
+        player.connection = None;
+        player.ready = false;
+        let room = player.room.clone();
+
+        if self.room_connections(&room).is_empty() {
+            self.close_room(&room);
+            return Some(LobbyEvent::RoomClosed(room));
+        }
+        self.broadcast_room_state(&room, network_state);
+        Some(LobbyEvent::Disconnected { player_id, room })
+    }
+
+    fn join<T>(
+        &mut self,
+        connection: &Connection,
+        name: &str,
+        room: &str,
+        session_token: Option<SessionToken>,
+        network_state: &NetworkStateImpl<T>,
+    ) -> Option<LobbyEvent> {
+        if self.player_id(connection).is_some() {
+            reject(connection, "Already joined a room", network_state);
+            return None;
+        }
+
+        let resumed_player = self.players.iter_mut().find(|(_, player)| {
+            player.connection.is_none() && Some(player.session_token) == session_token
+        });
+        if let Some((player_id, player)) = resumed_player {
+            let player_id = *player_id;
+            player.connection = Some(connection.clone());
+            let room = player.room.clone();
+            info!("Player {:?} resumed in room {}", player_id, room);
+            welcome(connection, player_id, player.session_token, network_state);
+            self.broadcast_room_state(&room, network_state);
+            return Some(LobbyEvent::Joined {
+                player_id,
+                room,
+                resumed: true,
+            });
+        }
+
+        if self.room_size(room) >= self.max_players_per_room {
+            reject(connection, "The room is full", network_state);
+            return None;
+        }
+        let player_id = PlayerId(self.next_player_id);
+        self.next_player_id += 1;
+        let session_token = SessionToken(Uuid::new_v4().as_u128());
+        self.players.insert(
+            player_id,
+            Player {
+                name: name.to_string(),
+                room: room.to_string(),
+                session_token,
+                connection: Some(connection.clone()),
+                ready: false,
+            },
+        );
+        self.rooms
+            .entry(room.to_string())
+            .or_default()
+            .push(player_id);
+        info!("{} joined room {} as {:?}", name, room, player_id);
+
+        welcome(connection, player_id, session_token, network_state);
+        self.broadcast_room_state(room, network_state);
+        Some(LobbyEvent::Joined {
+            player_id,
+            room: room.to_string(),
+            resumed: false,
+        })
+    }
+
+    fn leave<T>(
+        &mut self,
+        connection: &Connection,
+        network_state: &NetworkStateImpl<T>,
+    ) -> Option<LobbyEvent> {
+        let player_id = self.player_id(connection)?;
+        let player = self.players.remove(&player_id)?;
+        let room = player.room;
+        if let Some(player_ids) = self.rooms.get_mut(&room) {
+            player_ids.retain(|id| *id != player_id);
+        }
+
+        if self.room_connections(&room).is_empty() {
+            self.close_room(&room);
+            return Some(LobbyEvent::RoomClosed(room));
+        }
+        self.broadcast_room_state(&room, network_state);
+        Some(LobbyEvent::Left { player_id, room })
+    }
+
+    fn start_match<T>(
+        &mut self,
+        connection: &Connection,
+        network_state: &NetworkStateImpl<T>,
+    ) -> Option<LobbyEvent> {
+        let player_id = self.player_id(connection)?;
+        let room = self.players[&player_id].room.clone();
+        let room_state = self.room_state(&room)?;
+        if room_state.host != player_id {
+            reject(
+                connection,
+                "Only the host can start the match",
+                network_state,
+            );
+            return None;
+        }
+        if !room_state
+            .players
+            .iter()
+            .all(|player| player.ready || !player.connected)
+        {
+            reject(connection, "Not every player is ready", network_state);
+            return None;
+        }
+        Some(LobbyEvent::StartRequested(room))
+    }
+
+    fn close_room(&mut self, room: &str) {
+        for player_id in self.rooms.remove(room).into_iter().flatten() {
+            self.players.remove(&player_id);
+        }
+        info!("Closed room {}", room);
+    }
+
+    fn broadcast_room_state<T>(&self, room: &str, network_state: &NetworkStateImpl<T>) {
+        let Some(room_state) = self.room_state(room) else {
+            return;
+        };
+        for connection in self.room_connections(room) {
+            network_state.send_packet(
+                Packet::Lobby(LobbyPacket::RoomState(room_state.clone())),
+                &connection,
+            );
+        }
+    }
+}
+
+fn welcome<T>(
+    connection: &Connection,
+    player_id: PlayerId,
+    session_token: SessionToken,
+    network_state: &NetworkStateImpl<T>,
+) {
+    network_state.send_packet(
+        Packet::Lobby(LobbyPacket::Welcome {
+            player_id,
+            session_token,
+        }),
+        connection,
+    );
+}
+
+fn reject<T>(connection: &Connection, reason: &str, network_state: &NetworkStateImpl<T>) {
+    network_state.send_packet(
+        Packet::Lobby(LobbyPacket::Rejected(reason.to_string())),
+        connection,
+    );
+}
+
+/// Client side of the lobby, remembers the session token to resume the player after reconnects
+pub struct SessionClient {
+    name: String,
+    room: String,
+    player_id: Option<PlayerId>,
+    session_token: Option<SessionToken>,
+    room_state: Option<RoomState>,
+}
+
+impl SessionClient {
+    pub fn new(name: impl Into<String>, room: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            room: room.into(),
+            player_id: None,
+            session_token: None,
+            room_state: None,
+        }
+    }
+
+    pub fn join_packet(&self) -> LobbyPacket {
+        LobbyPacket::Join {
+            name: self.name.clone(),
+            room: self.room.clone(),
+            session_token: self.session_token,
+        }
+    }
+
+    pub fn ready_packet(&self, ready: bool) -> LobbyPacket {
+        LobbyPacket::SetReady(ready)
+    }
+
+    /// Only accepted from the host once every connected player is ready
+    pub fn start_match_packet(&self) -> LobbyPacket {
+        LobbyPacket::StartMatch
+    }
+
+    /// Whether the player hosts the room and every connected player in it is ready
+    pub fn can_start_match(&self) -> bool {
+        let (Some(player_id), Some(room_state)) = (self.player_id, &self.room_state) else {
+            return false;
+        };
+        room_state.host == player_id
+            && room_state
+                .players
+                .iter()
+                .all(|player| player.ready || !player.connected)
+    }
+
+    pub fn handle(&mut self, packet: &LobbyPacket) {
+        match packet {
+            LobbyPacket::Welcome {
+                player_id,
+                session_token,
+            } => {
+                info!("Joined room {} as {:?}", self.room, player_id);
+                self.player_id = Some(*player_id);
+                self.session_token = Some(*session_token);
+            }
+            LobbyPacket::RoomState(room_state) => self.room_state = Some(room_state.clone()),
+            LobbyPacket::Rejected(reason) => warn!("Lobby rejected request: {}", reason),
+            _ => warn!("Server sent a packet only clients may send: {:?}", packet),
+        }
+    }
+
+    pub fn player_id(&self) -> Option<PlayerId> {
+        self.player_id
+    }
+
+    pub fn room_state(&self) -> Option<&RoomState> {
+        self.room_state.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use message_io::node;
+
+    use super::*;
+    use crate::codec::BincodeCodec;
+    use crate::network_state::Signal;
+
+    fn network_state() -> NetworkStateImpl<()> {
+        let (node_handler, _) = node::split::<Signal>();
+        NetworkStateImpl::new(node_handler, Arc::new(BincodeCodec))
+    }
+
+    fn join(lobby: &mut Lobby, connection: &Connection, network_state: &NetworkStateImpl<()>) {
+        let packet = SessionClient::new("player", "room").join_packet();
+        lobby.handle_packet(connection, &packet, network_state);
+    }
+
+    #[test]
+    fn ready_check_requires_host_and_every_player_ready() {
+        let network_state = network_state();
+        let mut lobby = Lobby::new(4);
+        let host = Connection::new("127.0.0.1:1000".parse().unwrap());
+        let guest = Connection::new("127.0.0.1:1001".parse().unwrap());
+        join(&mut lobby, &host, &network_state);
+        join(&mut lobby, &guest, &network_state);
+        let client = SessionClient::new("player", "room");
+
+        let start = client.start_match_packet();
+        lobby.handle_packet(&host, &client.ready_packet(true), &network_state);
+        assert!(lobby.handle_packet(&host, &start, &network_state).is_none());
+
+        lobby.handle_packet(&guest, &client.ready_packet(true), &network_state);
+        assert!(lobby
+            .handle_packet(&guest, &start, &network_state)
+            .is_none());
+        assert!(matches!(
+            lobby.handle_packet(&host, &start, &network_state),
+            Some(LobbyEvent::StartRequested(room)) if room == "room"
+        ));
+    }
+
+    #[test]
+    fn client_only_starts_as_host_once_everyone_is_ready() {
+        let mut client = SessionClient::new("player", "room");
+        let player = |id, ready| PlayerInfo {
+            id: PlayerId(id),
+            name: String::from("player"),
+            ready,
+            connected: true,
+        };
+        let room_state = |players| {
+            LobbyPacket::RoomState(RoomState {
+                name: String::from("room"),
+                host: PlayerId(0),
+                players,
+            })
+        };
+        client.handle(&LobbyPacket::Welcome {
+            player_id: PlayerId(0),
+            session_token: SessionToken(0),
+        });
+
+        client.handle(&room_state(vec![player(0, true), player(1, false)]));
+        assert!(!client.can_start_match());
+        client.handle(&room_state(vec![player(0, true), player(1, true)]));
+        assert!(client.can_start_match());
+
+        client.handle(&LobbyPacket::Welcome {
+            player_id: PlayerId(1),
+            session_token: SessionToken(0),
+        });
+        assert!(!client.can_start_match());
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use crate::connection::Connection;
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    /// Only known for connections this side measures the clock of
+    pub rtt: Option<Duration>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub packets_in_per_second: f32,
+    pub packets_out_per_second: f32,
+    /// Share of sequenced datagrams that never arrived, always 0 on reliable transports
+    pub loss: f32,
+    pub last_seen: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkStats {
+    pub connections: Vec<(Connection, ConnectionStats)>,
+}
+
+/// Counts the traffic of a single endpoint, packet rates are measured over the last second
+#[derive(Debug, Clone)]
+pub(crate) struct TrafficCounter {
+    bytes_in: u64,
+    bytes_out: u64,
+    window_start: Instant,
+    window_packets_in: u32,
+    window_packets_out: u32,
+    packets_in_per_second: f32,
+    packets_out_per_second: f32,
+    last_seen: Option<Instant>,
+}
+
+impl TrafficCounter {
+    pub fn new() -> Self {
+        Self {
+            bytes_in: 0,
+            bytes_out: 0,
+            window_start: Instant::now(),
+            window_packets_in: 0,
+            window_packets_out: 0,
+            packets_in_per_second: 0.0,
+            packets_out_per_second: 0.0,
+            last_seen: None,
+        }
+    }
+
+    pub fn record_in(&mut self, bytes: usize) {
+        self.roll_window();
+        self.bytes_in += bytes as u64;
+        self.window_packets_in += 1;
+        self.last_seen = Some(Instant::now());
+    }
+
+    pub fn record_out(&mut self, bytes: usize) {
+        self.roll_window();
+        self.bytes_out += bytes as u64;
+        self.window_packets_out += 1;
+    }
+
+    /// Stats without the values the counter cannot know about
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            rtt: None,
+            bytes_in: self.bytes_in,
+            bytes_out: self.bytes_out,
+            packets_in_per_second: self.packets_in_per_second,
+            packets_out_per_second: self.packets_out_per_second,
+            loss: 0.0,
+            last_seen: self.last_seen,
+        }
+    }
+
+    fn roll_window(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        self.packets_in_per_second = self.window_packets_in as f32 / elapsed.as_secs_f32();
+        self.packets_out_per_second = self.window_packets_out as f32 / elapsed.as_secs_f32();
+        self.window_packets_in = 0;
+        self.window_packets_out = 0;
+        self.window_start = Instant::now();
+    }
+}
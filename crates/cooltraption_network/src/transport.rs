@@ -0,0 +1,57 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Message oriented transports, both ends of a connection have to use the same one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    #[default]
+    FramedTcp,
+    WebSocket,
+    /// Connectionless, packets are made reliable per `Channel` and are limited to about 64KiB
+    Udp,
+}
+
+impl Transport {
+    pub fn is_reliable(&self) -> bool {
+        !matches!(self, Transport::Udp)
+    }
+}
+
+impl From<Transport> for message_io::network::Transport {
+    fn from(transport: Transport) -> Self {
+        match transport {
+            Transport::FramedTcp => message_io::network::Transport::FramedTcp,
+            Transport::WebSocket => message_io::network::Transport::Ws,
+            Transport::Udp => message_io::network::Transport::Udp,
+        }
+    }
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::FramedTcp => write!(f, "framed-tcp"),
+            Transport::WebSocket => write!(f, "web-socket"),
+            Transport::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "framed-tcp" => Ok(Transport::FramedTcp),
+            "web-socket" => Ok(Transport::WebSocket),
+            "udp" => Ok(Transport::Udp),
+            _ => Err(format!(
+                "unknown transport '{}', expected framed-tcp, web-socket or udp",
+                s
+            )),
+        }
+    }
+}
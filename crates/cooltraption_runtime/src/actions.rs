@@ -0,0 +1,146 @@
+use cooltraption_simulation::action::{ActionType, ActionsOf};
+use cooltraption_simulation::system_sets::collision_set::Collider;
+use cooltraption_simulation::system_sets::lifecycle_set::despawn_all;
+use cooltraption_simulation::system_sets::physics_set::{Float, FromNum2, FromNum4, Mat2f, Vec2f};
+use cooltraption_simulation::system_sets::spatial_set::SpatialHash;
+use cooltraption_simulation::validation::{ActionChecks, ActionRules};
+use cooltraption_simulation::{
+    Acceleration, Commands, Drag, Force, Lifetime, NetIds, Owner, PhysicsBundle, Position, Query,
+    Res, ResMut, SpawnedAt, Tick, Velocity, Weight, World,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpawnBallAction {
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CircularForceAction {
+    pub position: Position,
+    pub strength: Float,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OutwardForceAction {
+    pub position: Position,
+    pub strength: Float,
+}
+
+/// Despawns the entities of the player that are within the radius around the position
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DespawnAction {
+    pub position: Position,
+    pub radius: Float,
+}
+
+impl ActionType for SpawnBallAction {
+    const NAME: &'static str = "spawn_ball";
+}
+
+impl ActionType for CircularForceAction {
+    const NAME: &'static str = "circular_force";
+}
+
+impl ActionType for OutwardForceAction {
+    const NAME: &'static str = "outward_force";
+}
+
+impl ActionType for DespawnAction {
+    const NAME: &'static str = "despawn";
+}
+
+/// Rules the server enforces for the actions above
+pub fn action_checks() -> ActionChecks {
+    let mut action_checks = ActionChecks::default();
+    action_checks
+        .add(|action: &SpawnBallAction, rules: &ActionRules| rules.check_bounds(&action.position))
+        .add(|action: &OutwardForceAction, rules: &ActionRules| {
+            rules.check_bounds(&action.position)?;
+            rules.check_strength(action.strength)
+        })
+        .add(|action: &CircularForceAction, rules: &ActionRules| {
+            rules.check_bounds(&action.position)?;
+            rules.check_strength(action.strength)
+        })
+        .add(|action: &DespawnAction, rules: &ActionRules| rules.check_bounds(&action.position));
+    action_checks
+}
+
+/// Ticks a ball lives for, a minute at the default tick rate
+const BALL_LIFETIME: u64 = 3600;
+
+pub fn apply_spawn_ball_action(
+    actions: Res<ActionsOf<SpawnBallAction>>,
+    tick: Res<Tick>,
+    mut net_ids: ResMut<NetIds>,
+    mut commands: Commands,
+) {
+    for (player, spawn_ball_action) in &actions.0 {
+        commands.spawn((
+            PhysicsBundle {
+                acc: Acceleration(Vec2f::from_num(0, 0)),
+                vel: Velocity(Vec2f::from_num(0, 0)),
+                pos: spawn_ball_action.position,
+                weight: Weight::default(),
+                force: Force::default(),
+                drag: Drag(Float::from_num(0.5)),
+            },
+            Owner(*player),
+            Collider::circle(Float::from_num(0.5)),
+            Lifetime(BALL_LIFETIME),
+            SpawnedAt(*tick),
+            net_ids.allocate(),
+        ));
+    }
+}
+
+/// Only despawns entities that are owned by the player that issued the action
+pub fn apply_despawn_action(world: &mut World) {
+    let (Some(actions), Some(spatial_hash)) = (
+        world.get_resource::<ActionsOf<DespawnAction>>(),
+        world.get_resource::<SpatialHash>(),
+    ) else {
+        return;
+    };
+    let mut despawned = vec![];
+    for (player, despawn_action) in &actions.0 {
+        despawned.extend(
+            spatial_hash
+                .within_radius(despawn_action.position.0, despawn_action.radius)
+                .into_iter()
+                .filter(|entity| {
+                    world
+                        .get::<Owner>(*entity)
+                        .is_some_and(|owner| owner.0 == *player)
+                }),
+        );
+    }
+    despawn_all(world, despawned);
+}
+
+/// Pushes every entity away from the position, stronger the further away it is
+pub fn apply_outward_force_action(
+    mut query: Query<(&Position, &mut Force)>,
+    actions: Res<ActionsOf<OutwardForceAction>>,
+) {
+    for (_, outward_force) in &actions.0 {
+        for (pos, mut force) in &mut query {
+            force.0 += (pos.0 - outward_force.position.0) * outward_force.strength;
+        }
+    }
+}
+
+/// Pushes every entity around the position
+pub fn apply_circular_force_action(
+    mut query: Query<(&Position, &mut Force)>,
+    actions: Res<ActionsOf<CircularForceAction>>,
+) {
+    for (_, circular_force) in &actions.0 {
+        let CircularForceAction { position, strength } = *circular_force;
+
+        for (pos, mut force) in &mut query {
+            force.0 += Mat2f::from_num(0, 1, -1, 0) * (position.0 - pos.0) * strength;
+        }
+    }
+}
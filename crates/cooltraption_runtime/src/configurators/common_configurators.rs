@@ -1,5 +1,6 @@
 use cgmath::Point2;
 use std::iter;
+use std::path::PathBuf;
 use std::sync::mpsc;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Sender;
@@ -8,27 +9,38 @@ use std::sync::MutexGuard;
 use cooltraption_input::input::{InputEvent, InputEventHandler, InputState};
 use cooltraption_network::builder::NodeEventHandlerBuilder;
 use cooltraption_network::client::connect;
+use cooltraption_network::codec::BincodeCodec;
 use cooltraption_network::network_state::NetworkStateEvent;
 use cooltraption_network::network_state::NetworkStateImpl;
 use cooltraption_network::packets::Packet;
+use cooltraption_network::reliability::Channel;
+use cooltraption_network::session::{LobbyPacket, SessionClient};
 use cooltraption_render::world_renderer::interpolator::Drawable;
 use cooltraption_simulation::action::Action;
 use cooltraption_simulation::action::ActionPacket;
+use cooltraption_simulation::replay::{Replay, ReplayPlayback, ReplayRecorder};
 use cooltraption_simulation::simulation_state::SimulationState;
+use cooltraption_simulation::state_transfer::StateTransfer;
 use cooltraption_simulation::ResetRequest;
 use cooltraption_simulation::SimulationPacket;
+use cooltraption_simulation::Tick;
 
+use crate::configurators::networking_config::NetworkingConfig;
 use crate::factories;
 use crate::factories::{create_input_handler, create_world_input_handler};
+use crate::lifecycle::ShutdownToken;
 use crate::render_component;
 use crate::RuntimeConfigurationBuilder;
 
 use cooltraption_common::overwritechannel::overwrite_channel;
+use cooltraption_common::types::TimePoint;
 use cooltraption_render::world_renderer::camera::controls::CameraView;
-use log::debug;
+use log::{debug, error, info};
 
 pub type InputEventCallback = Box<dyn FnMut(&InputEvent, &InputState) + 'static>;
 
+const CHECKSUM_INTERVAL_TICKS: u64 = 60;
+
 pub fn add_renderer(
     runtime_config_builder: &mut RuntimeConfigurationBuilder,
     input_action_sender: Sender<Action>,
@@ -44,49 +56,168 @@ pub fn add_renderer(
 
     let world_state_iterator = iter::from_fn(move || world_state_receiver.try_recv().ok());
 
+    let debug_stats = runtime_config_builder.debug_stats();
+    let simulation_debug_stats = debug_stats.clone();
+    runtime_config_builder
+        .simulation_run_options_builder()
+        .add_stats_callback(Box::new(move |stats| {
+            simulation_debug_stats.lock().unwrap().simulation = Some(*stats);
+        }));
 
-    runtime_config_builder.set_last_task(Box::new(move || {
-    let (camera_view_writer, camera_view_reader) = overwrite_channel::<CameraView>(CameraView {
-        position: Point2 { x: 0.0, y: 0.0 },
-        zoom: 1.0,
-    });
+    let shutdown_request = runtime_config_builder.shutdown_request();
+    runtime_config_builder.set_last_task(Box::new(move |renderer_shutdown| {
+        let (camera_view_writer, camera_view_reader) =
+            overwrite_channel::<CameraView>(CameraView {
+                position: Point2 { x: 0.0, y: 0.0 },
+                zoom: 1.0,
+            });
 
-    let input_event_callbacks: Vec<InputEventCallback> = vec![
-        Box::new(create_input_handler(
-            input_action_sender.clone(),
-            reset_sender,
-        )),
-        Box::new(create_world_input_handler(camera_view_reader, input_action_sender)),
-    ];
+        let input_event_callbacks: Vec<InputEventCallback> = vec![
+            Box::new(create_input_handler(
+                input_action_sender.clone(),
+                reset_sender,
+            )),
+            Box::new(create_world_input_handler(
+                camera_view_reader,
+                input_action_sender,
+            )),
+        ];
 
-    let input_event_handler = InputEventHandler::new(input_event_callbacks);
-        render_component::run_renderer(world_state_iterator, input_event_handler, camera_view_writer)
+        let input_event_handler = InputEventHandler::new(input_event_callbacks);
+        render_component::run_renderer(
+            world_state_iterator,
+            input_event_handler,
+            camera_view_writer,
+            debug_stats,
+            shutdown_request,
+            renderer_shutdown,
+        )
     }));
 }
 
+/// Saves every match that contained actions to `directory`, named after the time it ended
+pub fn add_replay_recorder(
+    runtime_config_builder: &mut RuntimeConfigurationBuilder,
+    directory: PathBuf,
+) {
+    runtime_config_builder
+        .simulation_run_options_builder()
+        .set_replay_recorder(ReplayRecorder::new(factories::SCHEDULE_NAME))
+        .add_replay_callback(Box::new(move |replay| {
+            if replay.action_packets.is_empty() {
+                return;
+            }
+            let path = directory.join(format!("{}.replay", TimePoint::now().millis()));
+            match replay.save(&path) {
+                Ok(()) => info!("Saved replay to {}", path.display()),
+                Err(e) => error!("{:#}", e),
+            }
+        }));
+}
+
+/// Plays back the replay instead of simulating local or remote actions
+pub fn add_replay_playback(
+    runtime_config_builder: &mut RuntimeConfigurationBuilder,
+    replay: Replay,
+) {
+    let playback = match ReplayPlayback::new(replay, factories::SCHEDULE_NAME) {
+        Ok(playback) => playback,
+        Err(e) => {
+            error!("{:#}", e);
+            return;
+        }
+    };
+    if let Err(e) = playback.restore_initial_state(runtime_config_builder.simulation_builder()) {
+        error!("{:#}", e);
+        return;
+    }
+    playback.configure_run_options(runtime_config_builder.simulation_run_options_builder());
+}
+
 pub fn add_networking_client(
     runtime_config_builder: &mut RuntimeConfigurationBuilder,
+    networking_config: NetworkingConfig,
     reset_sender: Sender<ResetRequest>,
 ) {
+    let server_address = networking_config.server_address;
+
     let mut node_event_handler_builder = NodeEventHandlerBuilder::default();
+    node_event_handler_builder.set_codec(BincodeCodec);
+    if let Some(network_conditions) = networking_config.network_conditions {
+        node_event_handler_builder.set_network_conditions(network_conditions);
+    }
     let (action_sender, action_receiver) = channel::<ActionPacket>();
+    let (input_ack_sender, input_ack_receiver) = channel::<Tick>();
+    let (snapshot_request_sender, snapshot_request_receiver) = channel::<Tick>();
+    let (state_transfer_sender, state_transfer_receiver) = channel::<StateTransfer>();
+    let mut session_client =
+        SessionClient::new(networking_config.player_name, networking_config.room);
+    // Actions received before the match was reset or transferred are part of the transfer
+    let mut in_match = false;
 
     let handler =
         move |event: &NetworkStateEvent<SimulationPacket>,
-              _locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>| {
-            if let NetworkStateEvent::Message(_connection, packet) = event {
+              locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>| {
+            if let NetworkStateEvent::Connected(connection) = event {
+                in_match = false;
+                locked_network_state
+                    .send_packet(Packet::Lobby(session_client.join_packet()), connection);
+            }
+            if let NetworkStateEvent::Message(connection, packet) = event {
                 match packet {
                     Packet::ChatMessage(msg) => {
                         debug!("Received Chat Message!: {}", msg.0);
                     }
                     Packet::ClientPacket(simulation_packet) => match simulation_packet {
                         SimulationPacket::ActionPacket(action_packet) => {
-                            action_sender.send(*action_packet).unwrap()
+                            if in_match {
+                                action_sender.send(action_packet.clone()).unwrap()
+                            }
                         }
                         SimulationPacket::ResetRequest(reset_request) => {
+                            in_match = true;
                             reset_sender.send(*reset_request).unwrap()
                         }
+                        SimulationPacket::StateTransfer(state_transfer) => {
+                            info!(
+                                "Joining running match at tick {}",
+                                state_transfer.snapshot.tick.0
+                            );
+                            in_match = true;
+                            state_transfer_sender.send(state_transfer.clone()).unwrap()
+                        }
+                        SimulationPacket::InputAck(tick) => input_ack_sender.send(*tick).unwrap(),
+                        SimulationPacket::StateRequest(tick) => {
+                            snapshot_request_sender.send(*tick).unwrap()
+                        }
+                        SimulationPacket::Desync(desync_report) => {
+                            error!("Server detected a desync: {:?}", desync_report);
+                        }
+                        SimulationPacket::InputBatch(_)
+                        | SimulationPacket::Checksum(_)
+                        | SimulationPacket::StateSnapshot(_) => {}
                     },
+                    Packet::Lobby(lobby_packet) => {
+                        session_client.handle(lobby_packet);
+                        let reply = match lobby_packet {
+                            LobbyPacket::Welcome { .. } if networking_config.ready => {
+                                Some(session_client.ready_packet(true))
+                            }
+                            // A running match would be restarted by another start request
+                            LobbyPacket::RoomState(_)
+                                if networking_config.start_when_ready
+                                    && !in_match
+                                    && session_client.can_start_match() =>
+                            {
+                                Some(session_client.start_match_packet())
+                            }
+                            _ => None,
+                        };
+                        if let Some(reply) = reply {
+                            locked_network_state.send_packet(Packet::Lobby(reply), connection);
+                        }
+                    }
+                    Packet::TimeSync(_) => {}
                 }
             }
         };
@@ -96,23 +227,85 @@ pub fn add_networking_client(
         .simulation_run_options_builder()
         .set_action_packets(Box::new(iter::from_fn(move || {
             action_receiver.try_recv().ok()
+        })))
+        .set_snapshot_requests(Box::new(iter::from_fn(move || {
+            snapshot_request_receiver.try_recv().ok()
+        })))
+        .set_state_transfers(Box::new(iter::from_fn(move || {
+            state_transfer_receiver.try_recv().ok()
+        })))
+        .set_input_acks(Box::new(iter::from_fn(move || {
+            input_ack_receiver.try_recv().ok()
         })));
 
     let node_event_handler = node_event_handler_builder.build();
     let concurrent_network_state = node_event_handler.concurrent_network_state();
+    let synced_clock = concurrent_network_state.lock().unwrap().clock();
 
-    let task = Box::new(|| connect("deni-ismailov.de:5001", node_event_handler));
+    let task = Box::new(move |shutdown: ShutdownToken| {
+        let node_handler = node_event_handler.node_handler();
+        shutdown.on_shutdown(move || node_handler.stop());
+        if let Err(e) = connect(
+            server_address.as_str(),
+            networking_config.transport,
+            networking_config.reconnect_policy,
+            node_event_handler,
+        ) {
+            error!("Could not connect to {}: {}", server_address, e);
+        }
+    });
     runtime_config_builder.add_task(task);
 
+    let checksum_network_state = concurrent_network_state.clone();
+    let snapshot_network_state = concurrent_network_state.clone();
+    let rtt_network_state = concurrent_network_state.clone();
+    let stats_network_state = concurrent_network_state.clone();
+    let debug_stats = runtime_config_builder.debug_stats();
+    debug_stats.lock().unwrap().network_source = Some(Box::new(move || {
+        stats_network_state.lock().unwrap().stats()
+    }));
     runtime_config_builder
         .simulation_run_options_builder()
-        .add_local_action_packet_callback(Box::new(move |local_action_packet| {
+        .set_synced_clock(synced_clock)
+        .set_apply_local_actions(false)
+        .set_input_delay(networking_config.input_delay)
+        .set_rtt_source(Box::new(move || rtt_network_state.lock().unwrap().rtt()))
+        // Batches repeat every unacknowledged action, so losing one is harmless
+        .add_input_batch_callback(Box::new(move |input_batch| {
             let locked_network_state = concurrent_network_state.lock().unwrap();
-            locked_network_state.send_packet(
-                Packet::<SimulationPacket>::ClientPacket(SimulationPacket::ActionPacket(
-                    *local_action_packet,
-                )),
-                locked_network_state.connections()[0],
-            )
+            if let Some(connection) = locked_network_state.connections().first() {
+                locked_network_state.send_packet_on(
+                    Packet::<SimulationPacket>::ClientPacket(SimulationPacket::InputBatch(
+                        input_batch.clone(),
+                    )),
+                    connection,
+                    Channel::UnreliableSequenced,
+                )
+            }
+        }))
+        .add_checksum_callback(Box::new(move |checksum_packet| {
+            if checksum_packet.tick.0 % CHECKSUM_INTERVAL_TICKS != 0 {
+                return;
+            }
+            let locked_network_state = checksum_network_state.lock().unwrap();
+            if let Some(connection) = locked_network_state.connections().first() {
+                locked_network_state.send_packet(
+                    Packet::<SimulationPacket>::ClientPacket(SimulationPacket::Checksum(
+                        *checksum_packet,
+                    )),
+                    connection,
+                )
+            }
+        }))
+        .add_snapshot_callback(Box::new(move |tick_snapshot| {
+            let locked_network_state = snapshot_network_state.lock().unwrap();
+            if let Some(connection) = locked_network_state.connections().first() {
+                locked_network_state.send_packet(
+                    Packet::<SimulationPacket>::ClientPacket(SimulationPacket::StateSnapshot(
+                        tick_snapshot.clone(),
+                    )),
+                    connection,
+                )
+            }
         }));
 }
@@ -1,6 +1,7 @@
 use crate::RuntimeConfigurationBuilder;
 
 pub mod common_configurators;
+pub mod networking_config;
 
 pub trait Configurator: ConfiguratorOnce {
     fn configure(&self, runtime_config: &mut RuntimeConfigurationBuilder);
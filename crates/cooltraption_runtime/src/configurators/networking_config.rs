@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use cooltraption_network::client::ReconnectPolicy;
+use cooltraption_network::conditions::NetworkConditions;
+use cooltraption_network::transport::Transport;
+use cooltraption_simulation::input::InputDelay;
+use smart_default::SmartDefault;
+
+#[derive(Debug, Clone, SmartDefault)]
+pub struct NetworkingConfig {
+    #[default(String::from("deni-ismailov.de:5001"))]
+    pub server_address: String,
+    /// Has to match the transport of the server
+    pub transport: Transport,
+    #[default(ReconnectPolicy::Retry { max_attempts: 5, delay: Duration::from_secs(1) })]
+    pub reconnect_policy: ReconnectPolicy,
+    #[default(String::from("player"))]
+    pub player_name: String,
+    /// Players in the same room play in the same match
+    #[default(String::from("lobby"))]
+    pub room: String,
+    /// Marks the player ready as soon as they joined the room
+    #[default(true)]
+    pub ready: bool,
+    /// Lets the host start the match once every player is ready, needed for
+    /// `StartPolicy::ReadyCheck`
+    #[default(true)]
+    pub start_when_ready: bool,
+    #[default(InputDelay::Adaptive { margin: 1, max: 10 })]
+    pub input_delay: InputDelay,
+    /// Degrades the connection of the client to test rollbacks and input delay
+    pub network_conditions: Option<NetworkConditions>,
+}
+
+impl NetworkingConfig {
+    pub fn remote(address: impl Into<String>) -> Self {
+        Self {
+            server_address: address.into(),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use std::sync::{Arc, Mutex};
+
+use cooltraption_network::stats::NetworkStats;
+use cooltraption_simulation::stats::SimulationStats;
+
+pub type NetworkStatsSource = Box<dyn Fn() -> NetworkStats + Send>;
+
+/// Numbers shown by the debug widget, filled in by the configurators that know about them
+#[derive(Default)]
+pub struct DebugStats {
+    pub simulation: Option<SimulationStats>,
+    /// Only queried while the debug widget is open
+    pub network_source: Option<NetworkStatsSource>,
+}
+
+pub type SharedDebugStats = Arc<Mutex<DebugStats>>;
@@ -2,20 +2,25 @@ use cgmath::{Point2, Vector2};
 use cooltraption_input::input::{InputEvent, InputState, KeyboardInputEvent};
 //use cooltraption_network as networking;
 //use cooltraption_network::client;
+use crate::actions::{
+    self, CircularForceAction, DespawnAction, OutwardForceAction, SpawnBallAction,
+};
 use cooltraption_render::world_renderer::interpolator::Transform;
 use cooltraption_render::world_renderer::interpolator::{Drawable, Id, Scale};
+use cooltraption_simulation::builders::SimulationImplBuilder;
 use cooltraption_simulation::ResetRequest;
 use cooltraption_simulation::{
-    action::{Action, CircularForceAction, SpawnBallAction},
+    action::{Action, ActionSet},
     system_sets::physics_set::{Float, FromNum2, Vec2f},
-    Entity, Position, QueryIter,
+    NetId, Position, QueryIter,
 };
 use cooltraption_window::window::winit::event::VirtualKeyCode;
 use std::sync::mpsc::{Sender, SyncSender};
 
 use cooltraption_simulation::{
-    system_sets::{action_set, physics_set},
-    IntoSystemConfig, IntoSystemConfigs, Schedule,
+    apply_system_buffers,
+    system_sets::{collision_set, lifecycle_set, physics_set, spatial_set},
+    IntoSystemConfig, IntoSystemConfigs, IntoSystemSetConfig, Schedule,
 };
 
 use cooltraption_common::overwritechannel::OverwriteChannelReader;
@@ -36,7 +41,7 @@ pub fn create_input_handler(
                         strength: Float::from_num(30),
                     };
                     input_action_sender
-                        .send(Action::CircularForce(circular_force_action))
+                        .send(Action::new(&circular_force_action))
                         .unwrap();
                 }
                 VirtualKeyCode::E => {
@@ -44,7 +49,7 @@ pub fn create_input_handler(
                         position: Position(Vec2f::from_num(0, 0)),
                     };
                     input_action_sender
-                        .send(Action::SpawnBall(spawn_ball_action))
+                        .send(Action::new(&spawn_ball_action))
                         .unwrap();
                 }
 
@@ -64,42 +69,52 @@ pub fn create_world_input_handler(
             input_event
         {
             if key_code == &VirtualKeyCode::F {
-                let state = camera_state.read();
-
-                let window_size = Vector2 {
-                    x: input_state.window_size.width as f32,
-                    y: input_state.window_size.height as f32,
-                };
-                let mouse_pos = Point2 {
-                    x: input_state.mouse_state.mouse_position().x as f32,
-                    y: input_state.mouse_state.mouse_position().y as f32,
-                };
-
-                let world_pos = state.world_pos(mouse_pos, window_size);
-                //println!("{:?},{:?},{:?}", mouse_pos, window_size, world_pos);
-
                 let spawn_ball_action = SpawnBallAction {
-                    position: Position(Vec2f::from_num(world_pos.x, world_pos.y)),
+                    position: cursor_world_position(&camera_state.read(), input_state),
                 };
                 input_action_sender
-                    .send(Action::SpawnBall(spawn_ball_action))
+                    .send(Action::new(&spawn_ball_action))
+                    .unwrap();
+            } else if key_code == &VirtualKeyCode::X {
+                let despawn_action = DespawnAction {
+                    position: cursor_world_position(&camera_state.read(), input_state),
+                    radius: Float::from_num(2),
+                };
+                input_action_sender
+                    .send(Action::new(&despawn_action))
                     .unwrap();
             }
         }
     }
 }
 
+/// Position in the world that the mouse cursor points at
+fn cursor_world_position(camera_view: &CameraView, input_state: &InputState) -> Position {
+    let window_size = Vector2 {
+        x: input_state.window_size.width as f32,
+        y: input_state.window_size.height as f32,
+    };
+    let mouse_pos = Point2 {
+        x: input_state.mouse_state.mouse_position().x as f32,
+        y: input_state.mouse_state.mouse_position().y as f32,
+    };
+
+    let world_pos = camera_view.world_pos(mouse_pos, window_size);
+    Position(Vec2f::from_num(world_pos.x, world_pos.y))
+}
+
+/// Entities without a `NetId` are not drawn, their ids are not stable across rollbacks
 pub fn sim_state_sender(
     world_state_sender: SyncSender<Vec<Drawable>>,
-) -> impl FnMut(QueryIter<'_, '_, (Entity, &Position), ()>) {
-    move |comp_iter: QueryIter<(Entity, &Position), ()>| {
+) -> impl FnMut(QueryIter<'_, '_, (&NetId, &Position), ()>) {
+    move |comp_iter: QueryIter<(&NetId, &Position), ()>| {
         let mut drawables = vec![];
-        for (entity, pos) in comp_iter {
+        for (net_id, pos) in comp_iter {
             let rpos = pos.0;
             let pos: Vector2<f32> = Vector2::new(rpos.x.0.to_num(), rpos.y.0.to_num());
             //pos /= 100.0;
             let drawable = Drawable {
-                id: Id(entity.index() as u64),
+                id: Id(net_id.0),
                 asset_name: String::from("dude"),
                 transform: Transform {
                     position: cooltraption_render::world_renderer::interpolator::Position(pos),
@@ -113,17 +128,54 @@ pub fn sim_state_sender(
     }
 }
 
+/// Identifies the schedule of `create_schedule` in replays, has to change along with it
+pub const SCHEDULE_NAME: &str = "cooltraption-physics-6";
+
 pub fn create_schedule() -> Schedule {
     let mut schedule = Schedule::default();
     schedule.add_system(physics_set::solve_movement.in_set(physics_set::PhysicsSet::Movement));
+    schedule.configure_set(ActionSet.before(physics_set::PhysicsSet::Movement));
+    // Actions see the positions at the start of the tick, later systems the moved positions
+    schedule.add_system(spatial_set::update_spatial_hash.before(ActionSet));
+    schedule.add_system(
+        spatial_set::update_spatial_hash
+            .after(physics_set::PhysicsSet::Movement)
+            .before(physics_set::PhysicsSet::CollisionDetection),
+    );
+    // Entities spawned by actions move in the tick they were spawned in
+    schedule.add_system(
+        apply_system_buffers
+            .after(ActionSet)
+            .before(physics_set::PhysicsSet::Movement),
+    );
     schedule.add_systems(
         (
-            action_set::apply_spawn_ball_action,
-            action_set::apply_outward_force_action,
-            action_set::apply_circular_force_action,
+            collision_set::detect_collisions,
+            collision_set::resolve_collisions,
         )
             .chain()
-            .before(physics_set::PhysicsSet::Movement),
+            .in_set(physics_set::PhysicsSet::CollisionDetection)
+            .after(physics_set::PhysicsSet::Movement),
+    );
+    schedule.add_systems(
+        (
+            lifecycle_set::expire_lifetimes,
+            lifecycle_set::enforce_world_bounds,
+            lifecycle_set::enforce_entity_cap,
+        )
+            .chain()
+            .in_set(lifecycle_set::LifecycleSet)
+            .after(physics_set::PhysicsSet::CollisionDetection),
     );
     schedule
 }
+
+/// Registers the handlers of the built-in actions, has to be called after `create_schedule`
+/// was set as the schedule
+pub fn register_actions(simulation_builder: &mut SimulationImplBuilder) {
+    simulation_builder
+        .register_action::<SpawnBallAction, _>(actions::apply_spawn_ball_action)
+        .register_action::<OutwardForceAction, _>(actions::apply_outward_force_action)
+        .register_action::<CircularForceAction, _>(actions::apply_circular_force_action)
+        .register_action::<DespawnAction, _>(actions::apply_despawn_action);
+}
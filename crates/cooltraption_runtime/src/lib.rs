@@ -5,9 +5,14 @@ use std::collections::VecDeque;
 use smart_default::SmartDefault;
 
 use cooltraption_simulation::builders::{SimulationImplBuilder, SimulationRunOptionsBuilder};
+use debug_stats::SharedDebugStats;
+use lifecycle::{GuardedThread, RuntimeError, ShutdownToken};
 
+pub mod actions;
 pub mod configurators;
+pub mod debug_stats;
 pub mod factories;
+pub mod lifecycle;
 mod render_component;
 
 #[derive(SmartDefault)]
@@ -16,6 +21,8 @@ pub struct RuntimeConfiguration {
     pub sim_run_options_builder: SimulationRunOptionsBuilder,
     pub tasks: VecDeque<Task>,
     pub last_task: Option<LastTask>,
+    pub shutdown_request: ShutdownToken,
+    pub debug_stats: SharedDebugStats,
 }
 
 #[derive(Default)]
@@ -45,37 +52,87 @@ impl RuntimeConfigurationBuilder {
         &mut self.runtime_config.sim_run_options_builder
     }
 
+    /// Triggering this token shuts down the whole runtime
+    pub fn shutdown_request(&self) -> ShutdownToken {
+        self.runtime_config.shutdown_request.clone()
+    }
+
+    /// Shared between the configurators that fill it and the debug widget
+    pub fn debug_stats(&self) -> SharedDebugStats {
+        self.runtime_config.debug_stats.clone()
+    }
+
     pub fn build(self) -> RuntimeConfiguration {
         self.runtime_config
     }
 }
 
-pub type Task = Box<dyn FnOnce() + Send + 'static>;
-pub type LastTask = Box<dyn FnOnce() + 'static>;
+/// Tasks run on their own thread and have to return once their `ShutdownToken` is triggered
+pub type Task = Box<dyn FnOnce(ShutdownToken) + Send + 'static>;
+/// The last task runs on the main thread and is shut down after the simulation
+pub type LastTask = Box<dyn FnOnce(ShutdownToken) + 'static>;
 
 #[derive(Default)]
 pub struct Runtime {}
 
 impl Runtime {
-    pub fn run(config: RuntimeConfiguration) {
-        let mut task_handles = vec![];
-        let run_options = config.sim_run_options_builder.build();
-        let sim_handle = std::thread::spawn(|| {
-            let mut simulation = config.sim_builder.build();
-            simulation.run(run_options);
+    /// Runs until a shutdown is requested, then tears down the tasks (e.g. networking),
+    /// the simulation and the last task (e.g. the renderer) in that order.
+    pub fn run(config: RuntimeConfiguration) -> Result<(), RuntimeError> {
+        let shutdown_request = config.shutdown_request;
+        let tasks_shutdown = ShutdownToken::default();
+        let simulation_shutdown = ShutdownToken::default();
+        let last_task_shutdown = ShutdownToken::default();
+
+        let mut sim_run_options_builder = config.sim_run_options_builder;
+        let simulation_stop = simulation_shutdown.clone();
+        sim_run_options_builder.set_stop_signal(Box::new(move || simulation_stop.is_triggered()));
+        let run_options = sim_run_options_builder.build();
+        let sim_builder = config.sim_builder;
+        let sim_thread = GuardedThread::spawn(
+            String::from("simulation"),
+            shutdown_request.clone(),
+            move || {
+                let mut simulation = sim_builder.build();
+                simulation.run(run_options);
+            },
+        );
+
+        let task_threads: Vec<_> = config
+            .tasks
+            .into_iter()
+            .enumerate()
+            .map(|(index, task)| {
+                let task_shutdown = tasks_shutdown.clone();
+                GuardedThread::spawn(
+                    format!("task-{}", index),
+                    shutdown_request.clone(),
+                    move || task(task_shutdown),
+                )
+            })
+            .collect();
+
+        let teardown_request = shutdown_request.clone();
+        let teardown_last_task = last_task_shutdown.clone();
+        let teardown = std::thread::spawn(move || {
+            teardown_request.wait();
+
+            tasks_shutdown.trigger();
+            let mut results: Vec<_> = task_threads.into_iter().map(GuardedThread::join).collect();
+
+            simulation_shutdown.trigger();
+            results.push(sim_thread.join());
+
+            teardown_last_task.trigger();
+            results.into_iter().collect::<Result<(), RuntimeError>>()
         });
-        task_handles.push(sim_handle);
-        for task in config.tasks {
-            let task_handle = std::thread::spawn(task);
-            task_handles.push(task_handle);
-        }
+
         if let Some(last_task) = config.last_task {
-            last_task();
+            last_task(last_task_shutdown);
+            shutdown_request.trigger();
         }
 
-        for task in task_handles {
-            task.join().unwrap();
-        }
+        teardown.join().expect("teardown not to panic")
     }
 
     pub fn config() -> RuntimeConfiguration {
@@ -0,0 +1,133 @@
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use log::error;
+
+type ShutdownCallback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct ShutdownState {
+    triggered: bool,
+    callbacks: Vec<ShutdownCallback>,
+}
+
+/// Cheap to clone handle to a one-shot shutdown signal
+#[derive(Clone, Default)]
+pub struct ShutdownToken {
+    state: Arc<(Mutex<ShutdownState>, Condvar)>,
+}
+
+impl ShutdownToken {
+    pub fn trigger(&self) {
+        let (state, condvar) = &*self.state;
+        let callbacks = {
+            let mut state = state.lock().unwrap();
+            if state.triggered {
+                return;
+            }
+            state.triggered = true;
+            std::mem::take(&mut state.callbacks)
+        };
+        condvar.notify_all();
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.state.0.lock().unwrap().triggered
+    }
+
+    pub fn wait(&self) {
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+        while !state.triggered {
+            state = condvar.wait(state).unwrap();
+        }
+    }
+
+    /// Runs the callback once the token is triggered, or right away if it already was
+    pub fn on_shutdown(&self, callback: impl FnOnce() + Send + 'static) {
+        {
+            let mut state = self.state.0.lock().unwrap();
+            if !state.triggered {
+                state.callbacks.push(Box::new(callback));
+                return;
+            }
+        }
+        callback();
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    Panicked { thread: String, message: String },
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::Panicked { thread, message } => {
+                write!(f, "Thread '{}' panicked: {}", thread, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+pub(crate) struct GuardedThread {
+    name: String,
+    handle: JoinHandle<()>,
+}
+
+impl GuardedThread {
+    /// Spawns a thread that triggers `on_panic` if `f` panics
+    pub(crate) fn spawn(
+        name: String,
+        on_panic: ShutdownToken,
+        f: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        let handle = thread::Builder::new()
+            .name(name.clone())
+            .spawn(move || {
+                let _guard = PanicGuard(on_panic);
+                f()
+            })
+            .expect("os to allow spawning threads");
+        Self { name, handle }
+    }
+
+    pub(crate) fn join(self) -> Result<(), RuntimeError> {
+        self.handle.join().map_err(|payload| {
+            let error = RuntimeError::Panicked {
+                thread: self.name,
+                message: panic_message(payload),
+            };
+            error!("{}", error);
+            error
+        })
+    }
+}
+
+struct PanicGuard(ShutdownToken);
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.trigger();
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic payload")
+    }
+}
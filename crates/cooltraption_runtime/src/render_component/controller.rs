@@ -1,6 +1,7 @@
 use super::controls::{ButtonMap, KeyboardState, MouseState};
 use super::debug_widget::DebugWidget;
 use super::CameraViewHandler;
+use crate::debug_stats::SharedDebugStats;
 use cgmath::num_traits::*;
 use cgmath::*;
 use cooltraption_render::gui::{GuiActionDispatcher, WidgetId};
@@ -20,6 +21,7 @@ pub struct InputStateEventHandler {
     mouse_state: MouseState,
     gui: GuiActionDispatcher,
     debug_widget: Option<WidgetId>,
+    debug_stats: SharedDebugStats,
     target_pos: Point2<f32>,
     target_zoom: f32,
     view: CameraView,
@@ -31,6 +33,7 @@ impl Controller {
     pub fn new(
         gui: GuiActionDispatcher,
         camera_moved_event_publisher: Vec<CameraViewHandler>,
+        debug_stats: SharedDebugStats,
     ) -> (Self, InputStateEventHandler) {
         let (send, recv) = std::sync::mpsc::channel();
 
@@ -40,6 +43,7 @@ impl Controller {
             mouse_state: Default::default(),
             gui,
             debug_widget: None,
+            debug_stats,
             target_pos: Point2::origin(),
             target_zoom: 1.0,
             view: Default::default(),
@@ -122,8 +126,9 @@ impl EventHandler<WinitEvent<'_, '_>, WindowContext<'_>> for InputStateEventHand
                                     self.gui.close(debug_widget);
                                     self.debug_widget = None;
                                 } else {
-                                    self.debug_widget =
-                                        Some(self.gui.open(Box::<DebugWidget>::default()));
+                                    self.debug_widget = Some(self.gui.open(Box::new(
+                                        DebugWidget::new(self.debug_stats.clone()),
+                                    )));
                                 }
                             }
                         }
@@ -5,6 +5,8 @@ use cooltraption_window::window::{WindowContext, WinitEvent};
 use std::fmt::{Display, Formatter};
 use std::time::Instant;
 
+use crate::debug_stats::SharedDebugStats;
+
 struct FpsCounter {
     min: f32,
     max: f32,
@@ -61,16 +63,59 @@ pub struct DebugWidget {
     window_size: PhysicalSize<u32>,
     tps: FpsCounter,
     fps: FpsCounter,
+    debug_stats: SharedDebugStats,
     is_open: bool,
 }
 
-impl Default for DebugWidget {
-    fn default() -> Self {
+impl DebugWidget {
+    pub fn new(debug_stats: SharedDebugStats) -> Self {
         Self {
             is_open: true,
             window_size: Default::default(),
             tps: FpsCounter::new(),
             fps: FpsCounter::new(),
+            debug_stats,
+        }
+    }
+
+    fn show_stats(debug_stats: &SharedDebugStats, ui: &mut egui::Ui) {
+        let debug_stats = debug_stats.lock().unwrap();
+        if let Some(simulation) = &debug_stats.simulation {
+            ui.add_space(10.0);
+            ui.label(format!("Tick {}", simulation.tick.0));
+            ui.label(format!("Input delay {} ticks", simulation.input_delay));
+            ui.label(format!(
+                "Rollbacks {} ({} ticks resimulated)",
+                simulation.rollbacks, simulation.resimulated_ticks
+            ));
+        }
+
+        let Some(network_source) = &debug_stats.network_source else {
+            return;
+        };
+        for (connection, stats) in network_source().connections {
+            ui.add_space(10.0);
+            ui.label(format!("{}", connection.socket_addr()));
+            match stats.rtt {
+                Some(rtt) => ui.label(format!("RTT {}ms", rtt.as_millis())),
+                None => ui.label("RTT -"),
+            };
+            ui.label(format!(
+                "In {} B ({:.0} packets/s)",
+                stats.bytes_in, stats.packets_in_per_second
+            ));
+            ui.label(format!(
+                "Out {} B ({:.0} packets/s)",
+                stats.bytes_out, stats.packets_out_per_second
+            ));
+            ui.label(format!("Loss {:.1}%", stats.loss * 100.0));
+            match stats.last_seen {
+                Some(last_seen) => ui.label(format!(
+                    "Last seen {:.1}s ago",
+                    last_seen.elapsed().as_secs_f32()
+                )),
+                None => ui.label("Last seen -"),
+            };
         }
     }
 }
@@ -99,6 +144,8 @@ impl Widget for DebugWidget {
 
                 ui.label(format!("FPS {}", self.fps));
                 ui.label(format!("TPS {}", self.tps));
+
+                Self::show_stats(&self.debug_stats, ui);
             });
 
         self.is_open
@@ -1,6 +1,7 @@
 pub mod controller;
 mod controls;
 mod debug_widget;
+mod shutdown_handler;
 
 use controller::Controller;
 use cooltraption_common::overwritechannel::OverwriteChannelWriter;
@@ -9,7 +10,8 @@ use cooltraption_render::renderer::WgpuInitializer;
 use cooltraption_render::world_renderer::asset_bundle::{FileAssetLoader, LoadAssetBundle};
 use cooltraption_render::world_renderer::texture_atlas::TextureAtlasBuilder;
 use cooltraption_render::world_renderer::WorldRendererInitializer;
-use cooltraption_window::window::{WindowEventHandler, WinitEventLoopHandler};
+use cooltraption_window::window::WinitEventLoopHandler;
+use shutdown_handler::ShutdownEventHandler;
 use std::env;
 use std::time::Duration;
 
@@ -17,6 +19,9 @@ use cooltraption_input::input::InputEventHandler;
 use cooltraption_render::world_renderer::camera::controls::CameraView;
 use cooltraption_render::world_renderer::interpolator::Drawable;
 
+use crate::debug_stats::SharedDebugStats;
+use crate::lifecycle::ShutdownToken;
+
 type CameraViewHandler = Box<dyn FnMut(&CameraView)>;
 
 #[tokio::main]
@@ -24,6 +29,9 @@ pub async fn run_renderer<I>(
     state_iterator: I,
     input_event_handler: InputEventHandler,
     overwrite_channel_writer: OverwriteChannelWriter<CameraView>,
+    debug_stats: SharedDebugStats,
+    shutdown_request: ShutdownToken,
+    renderer_shutdown: ShutdownToken,
 ) where
     I: Iterator<Item = Vec<Drawable>> + 'static,
 {
@@ -33,7 +41,7 @@ pub async fn run_renderer<I>(
             overwrite_channel_writer.write(*event);
         })];
     let (controller, controller_event_handler) =
-        Controller::new(dispatcher, camera_state_callbacks);
+        Controller::new(dispatcher, camera_state_callbacks, debug_stats);
 
     let world_renderer = {
         let mut texture_atlas_builder = TextureAtlasBuilder::default();
@@ -64,7 +72,10 @@ pub async fn run_renderer<I>(
     let mut event_loop_handler = WinitEventLoopHandler::default();
 
     event_loop_handler.register_event_handler(Box::new(input_event_handler));
-    event_loop_handler.register_event_handler(Box::new(WindowEventHandler {}));
+    event_loop_handler.register_event_handler(Box::new(ShutdownEventHandler {
+        shutdown_request,
+        renderer_shutdown,
+    }));
     event_loop_handler.register_event_handler(Box::new(gui_event_handler));
     event_loop_handler.register_event_handler(Box::new(wgpu_initializer));
     event_loop_handler.register_event_handler(Box::new(controller_event_handler));
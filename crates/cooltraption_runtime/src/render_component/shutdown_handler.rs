@@ -0,0 +1,30 @@
+use cooltraption_window::events::EventHandler;
+use cooltraption_window::window::winit::event_loop::ControlFlow;
+use cooltraption_window::window::{winit, WindowContext, WinitEvent};
+
+use crate::lifecycle::ShutdownToken;
+
+/// Replaces the `WindowEventHandler`: closing the window requests a runtime shutdown,
+/// but the window only exits once the runtime tore down everything else.
+pub struct ShutdownEventHandler {
+    pub shutdown_request: ShutdownToken,
+    pub renderer_shutdown: ShutdownToken,
+}
+
+impl EventHandler<WinitEvent<'_, '_>, WindowContext<'_>> for ShutdownEventHandler {
+    fn handle_event(&mut self, event: &mut WinitEvent, context: &mut WindowContext) {
+        if let winit::event::Event::WindowEvent {
+            event: winit::event::WindowEvent::CloseRequested,
+            window_id,
+        } = event.0
+        {
+            if window_id == &context.window.id() {
+                self.shutdown_request.trigger();
+            }
+        }
+
+        if self.renderer_shutdown.is_triggered() {
+            *context.control_flow = ControlFlow::Exit;
+        }
+    }
+}
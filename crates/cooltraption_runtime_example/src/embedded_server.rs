@@ -0,0 +1,40 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use cooltraption_runtime::actions::action_checks;
+use cooltraption_runtime::lifecycle::ShutdownToken;
+use cooltraption_runtime::RuntimeConfigurationBuilder;
+use cooltraption_server::{Server, ServerConfig};
+use log::error;
+
+/// Runs the server in a task and returns the address the client can reach it on, nothing is
+/// added if the server cannot bind its address
+pub fn add_embedded_server(
+    runtime_config_builder: &mut RuntimeConfigurationBuilder,
+    server_config: ServerConfig,
+) -> Option<SocketAddr> {
+    let bind_address = server_config.bind_address;
+    let server = match Server::bind(server_config, action_checks()) {
+        Ok(server) => server,
+        Err(e) => {
+            error!(
+                "Could not bind the embedded server to {}: {}",
+                bind_address, e
+            );
+            return None;
+        }
+    };
+    let mut server_address = server.local_addr();
+    if server_address.ip().is_unspecified() {
+        server_address.set_ip(match server_address {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+
+    runtime_config_builder.add_task(Box::new(move |shutdown: ShutdownToken| {
+        let node_handler = server.node_handler();
+        shutdown.on_shutdown(move || node_handler.stop());
+        server.run()
+    }));
+    Some(server_address)
+}
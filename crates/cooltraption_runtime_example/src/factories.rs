@@ -1,5 +1,6 @@
+use cooltraption_runtime::actions::SpawnBallAction;
 use cooltraption_runtime::RuntimeConfigurationBuilder;
-use cooltraption_simulation::action::{Action, SpawnBallAction};
+use cooltraption_simulation::action::Action;
 use std::iter;
 
 #[allow(dead_code)]
@@ -8,7 +9,7 @@ fn sometimes_spawn_ball(rt_config_builder: &mut RuntimeConfigurationBuilder) {
     let boxed_it = Box::new(iter::from_fn(move || {
         i += 1;
         if i % 10 == 0 {
-            return Some(Action::SpawnBall(SpawnBallAction {
+            return Some(Action::new(&SpawnBallAction {
                 position: Default::default(),
             }));
         }
@@ -1,17 +1,28 @@
+use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::{env, iter};
 
 use cooltraption_runtime::configurators::common_configurators::{
-    add_networking_client, add_renderer,
+    add_networking_client, add_renderer, add_replay_playback, add_replay_recorder,
 };
+use cooltraption_runtime::configurators::networking_config::NetworkingConfig;
 use cooltraption_runtime::configurators::{
     ConfiguratorOnce, ConfiguratorOncePipeline, ConfiguratorPipeline,
 };
-use cooltraption_runtime::factories::create_schedule;
+use cooltraption_runtime::factories::{create_schedule, register_actions};
 use cooltraption_runtime::{Runtime, RuntimeConfigurationBuilder};
+use cooltraption_server::ServerConfig;
 use cooltraption_simulation::action::Action;
+use cooltraption_simulation::replay::Replay;
+use cooltraption_simulation::system_sets::lifecycle_set::{
+    BoundaryMode, CapPolicy, EntityCap, WorldBounds,
+};
+use cooltraption_simulation::system_sets::physics_set::{FromNum2, Vec2f};
 use cooltraption_simulation::ResetRequest;
+use embedded_server::add_embedded_server;
+use log::error;
 
+pub mod embedded_server;
 pub mod factories;
 
 fn main() {
@@ -32,6 +43,26 @@ fn main() {
 }
 
 fn runtime_example() {
+    let mut server_address = None;
+    let mut record_directory = None;
+    let mut replay_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record_directory = args.next().map(PathBuf::from),
+            "--replay" => replay_path = args.next().map(PathBuf::from),
+            _ => server_address = Some(arg),
+        }
+    }
+
+    let replay = match replay_path.as_deref().map(Replay::load).transpose() {
+        Ok(replay) => replay,
+        Err(error) => {
+            error!("{:#}", error);
+            std::process::exit(1);
+        }
+    };
+
     let (input_action_sender, input_action_receiver) = channel::<Action>();
     let (reset_sender, reset_receiver) = channel::<ResetRequest>();
 
@@ -50,7 +81,16 @@ fn runtime_example() {
     let add_schedule_configurator = |rt_config: &mut RuntimeConfigurationBuilder| {
         rt_config
             .simulation_builder()
-            .set_schedule(create_schedule());
+            .set_schedule(create_schedule())
+            .set_world_bounds(WorldBounds::centered(
+                Vec2f::from_num(1000, 1000),
+                BoundaryMode::Bounce,
+            ))
+            .set_entity_cap(EntityCap {
+                max_entities: 2000,
+                policy: CapPolicy::DespawnOldest,
+            });
+        register_actions(rt_config.simulation_builder());
     };
     let cloned_reset_sender = reset_sender.clone();
     let render_configurator = move |rt_config: &mut RuntimeConfigurationBuilder| {
@@ -70,19 +110,52 @@ fn runtime_example() {
     };
     configurator_pipeline
         .add_configurator(add_schedule_configurator)
-        .add_configurator(render_configurator)
-        .add_configurator(move |rt_config: &mut RuntimeConfigurationBuilder| {
-            add_networking_client(rt_config, reset_sender.clone())
-        });
+        .add_configurator(render_configurator);
+    if let Some(record_directory) = record_directory {
+        configurator_pipeline.add_configurator(
+            move |rt_config: &mut RuntimeConfigurationBuilder| {
+                add_replay_recorder(rt_config, record_directory.clone())
+            },
+        );
+    }
+    // A replay is played back offline instead of joining a match
+    if replay.is_none() {
+        configurator_pipeline.add_configurator(
+            move |rt_config: &mut RuntimeConfigurationBuilder| {
+                // Without a server address a local server is hosted, so the example also works
+                // offline and others can join via LAN
+                let networking_config = match &server_address {
+                    Some(server_address) => NetworkingConfig::remote(server_address),
+                    None => match add_embedded_server(rt_config, ServerConfig::default()) {
+                        Some(server_address) => {
+                            NetworkingConfig::remote(server_address.to_string())
+                        }
+                        None => return,
+                    },
+                };
+                add_networking_client(rt_config, networking_config, reset_sender.clone())
+            },
+        );
+    }
 
     configurator_once_pipeline
         .add_configurator_once(configurator_pipeline)
         .add_configurator_once(input_action_configurator)
         .add_configurator_once(reset_setter);
+    if let Some(replay) = replay {
+        configurator_once_pipeline.add_configurator_once(
+            move |rt_config: &mut RuntimeConfigurationBuilder| {
+                add_replay_playback(rt_config, replay)
+            },
+        );
+    }
 
     configurator_once_pipeline
         .boxed()
         .configure_once(&mut runtime_config_builder);
 
-    Runtime::run(runtime_config_builder.build());
+    if let Err(error) = Runtime::run(runtime_config_builder.build()) {
+        error!("Runtime stopped with an error: {}", error);
+        std::process::exit(1);
+    }
 }
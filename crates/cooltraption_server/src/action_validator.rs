@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use cooltraption_common::types::PlayerId;
+use cooltraption_simulation::action::Action;
+use cooltraption_simulation::validation::{ActionChecks, ActionRules, ValidationError};
+
+/// Token bucket that refills `max_actions_per_second` tokens per second
+struct RateLimiter {
+    tokens: f32,
+    last_refill: Instant,
+}
+
+/// Checks the actions of every player against the configured rules before they are scheduled
+pub struct ActionValidator {
+    rules: ActionRules,
+    checks: ActionChecks,
+    rate_limiters: HashMap<PlayerId, RateLimiter>,
+    violations: HashMap<PlayerId, u32>,
+}
+
+impl ActionValidator {
+    pub fn new(rules: ActionRules, checks: ActionChecks) -> Self {
+        Self {
+            rules,
+            checks,
+            rate_limiters: Default::default(),
+            violations: Default::default(),
+        }
+    }
+
+    pub fn validate(&mut self, player: PlayerId, action: &Action) -> Result<(), ValidationError> {
+        let result = self
+            .check_rules(action)
+            .and_then(|_| self.take_token(player));
+        if let Err(e) = &result {
+            if e.is_violation() {
+                *self.violations.entry(player).or_default() += 1;
+            }
+        }
+        result
+    }
+
+    /// Counts a packet the player was not allowed to send
+    pub fn add_violation(&mut self, player: PlayerId) {
+        *self.violations.entry(player).or_default() += 1;
+    }
+
+    /// Whether the player broke the rules often enough to be disconnected
+    pub fn exceeded_violations(&self, player: PlayerId) -> bool {
+        self.violations.get(&player).copied().unwrap_or_default() >= self.rules.max_violations
+    }
+
+    pub fn remove_player(&mut self, player: PlayerId) {
+        self.rate_limiters.remove(&player);
+        self.violations.remove(&player);
+    }
+
+    fn check_rules(&self, action: &Action) -> Result<(), ValidationError> {
+        self.checks.check(action, &self.rules)
+    }
+
+    fn take_token(&mut self, player: PlayerId) -> Result<(), ValidationError> {
+        let max_tokens = self.rules.max_actions_per_second as f32;
+        let rate_limiter = self
+            .rate_limiters
+            .entry(player)
+            .or_insert_with(|| RateLimiter {
+                tokens: max_tokens,
+                last_refill: Instant::now(),
+            });
+        let elapsed = rate_limiter.last_refill.elapsed().as_secs_f32();
+        rate_limiter.tokens = (rate_limiter.tokens + elapsed * max_tokens).min(max_tokens);
+        rate_limiter.last_refill = Instant::now();
+
+        if rate_limiter.tokens < 1.0 {
+            return Err(ValidationError::RateLimited);
+        }
+        rate_limiter.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cooltraption_simulation::action::ActionType;
+    use cooltraption_simulation::system_sets::physics_set::Float;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct PushAction {
+        strength: i32,
+    }
+
+    impl ActionType for PushAction {
+        const NAME: &'static str = "push";
+    }
+
+    fn validator(rules: ActionRules) -> ActionValidator {
+        let mut checks = ActionChecks::default();
+        checks.add(|push: &PushAction, rules: &ActionRules| {
+            rules.check_strength(Float::from_num(push.strength))
+        });
+        ActionValidator::new(rules, checks)
+    }
+
+    fn push(strength: i32) -> Action {
+        Action::new(&PushAction { strength })
+    }
+
+    #[test]
+    fn actions_above_the_rate_limit_are_throttled() {
+        let mut action_validator = validator(ActionRules {
+            max_actions_per_second: 2,
+            max_violations: 1,
+            ..Default::default()
+        });
+        let player = PlayerId(0);
+        assert!(action_validator.validate(player, &push(1)).is_ok());
+        assert!(action_validator.validate(player, &push(1)).is_ok());
+        assert!(matches!(
+            action_validator.validate(player, &push(1)),
+            Err(ValidationError::RateLimited)
+        ));
+        // Throttling does not count as a violation, and other players keep their own budget
+        assert!(!action_validator.exceeded_violations(player));
+        assert!(action_validator.validate(PlayerId(1), &push(1)).is_ok());
+    }
+
+    #[test]
+    fn invalid_actions_count_as_violations() {
+        let mut action_validator = validator(ActionRules {
+            max_force_strength: 10.0,
+            max_violations: 2,
+            ..Default::default()
+        });
+        let player = PlayerId(0);
+        assert!(matches!(
+            action_validator.validate(player, &push(20)),
+            Err(ValidationError::TooStrong { .. })
+        ));
+        assert!(!action_validator.exceeded_violations(player));
+
+        let unknown = Action {
+            name: String::from("unknown"),
+            version: 0,
+            payload: vec![],
+        };
+        assert!(matches!(
+            action_validator.validate(player, &unknown),
+            Err(ValidationError::Undecodable { .. })
+        ));
+        assert!(action_validator.exceeded_violations(player));
+
+        action_validator.remove_player(player);
+        assert!(!action_validator.exceeded_violations(player));
+        action_validator.add_violation(player);
+        action_validator.add_violation(player);
+        assert!(action_validator.exceeded_violations(player));
+    }
+}
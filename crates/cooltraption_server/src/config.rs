@@ -0,0 +1,71 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::ValueEnum;
+use cooltraption_network::transport::Transport;
+use cooltraption_simulation::clock::TickRate;
+use cooltraption_simulation::validation::ActionRules;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
+
+/// Decides when the server tells all clients of a room to reset and start a new match.
+/// The host of a room can always start a match once every player is ready.
+/// Players joining a running match receive its current state instead of restarting it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartPolicy {
+    /// Start a match once the first player joins, later players join the running match
+    #[default]
+    OnJoin,
+    /// Start the match once `max_players` joined the room
+    WhenFull,
+    /// Only start the match when the host requests it and every player is ready
+    ReadyCheck,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SmartDefault)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ServerConfig {
+    /// Port 0 binds an ephemeral port
+    #[default(SocketAddr::from(([0, 0, 0, 0], 5001)))]
+    pub bind_address: SocketAddr,
+    pub transport: Transport,
+    pub tick_rate: TickRate,
+    /// Minimum ticks between the server receiving an action and the action being executed,
+    /// clients may schedule their actions further ahead
+    #[default(3)]
+    pub input_delay: u64,
+    /// Actions issued more ticks ago than this are rejected
+    #[default(30)]
+    pub max_action_age: u64,
+    /// Actions issued for more ticks than this beyond the current tick plus the input delay
+    /// are rejected, has to cover the highest input delay of the clients
+    #[default(30)]
+    pub max_action_lead: u64,
+    /// Ticks of actions kept per match to bring late joiners up to date, has to exceed the
+    /// rollback depth of the clients
+    #[default(600)]
+    pub action_log_ticks: u64,
+    pub action_rules: ActionRules,
+    /// Players per room
+    #[default(8)]
+    pub max_players: usize,
+    pub start_policy: StartPolicy,
+    #[default(LevelFilter::Info)]
+    pub log_level: LevelFilter,
+    /// Chat message sent to every player that joins
+    pub greeting: Option<String>,
+}
+
+impl ServerConfig {
+    /// Reads a YAML config file, missing fields keep their default value
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Could not parse config file {}", path.display()))
+    }
+}
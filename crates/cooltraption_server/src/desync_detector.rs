@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, HashMap};
+
+use cooltraption_network::connection::Connection;
+use cooltraption_simulation::desync::{ChecksumPacket, DesyncReport};
+use cooltraption_simulation::snapshot::StateChecksum;
+use cooltraption_simulation::Tick;
+
+/// Checksums of ticks this far behind the newest reported tick are dropped, so ticks that some
+/// peer never reports do not pile up
+const CHECKSUM_WINDOW_TICKS: u64 = 600;
+
+#[derive(Default)]
+pub struct DesyncDetector {
+    checksums: BTreeMap<Tick, HashMap<Connection, StateChecksum>>,
+    first_desync: Option<Tick>,
+    newest_tick: Tick,
+}
+
+impl DesyncDetector {
+    /// Returns a report if the checksum reveals a desync that happened before every
+    /// desync reported so far
+    pub fn add_checksum(
+        &mut self,
+        connection: &Connection,
+        checksum_packet: &ChecksumPacket,
+        peer_count: usize,
+    ) -> Option<DesyncReport> {
+        let ChecksumPacket { tick, checksum } = *checksum_packet;
+        if self
+            .first_desync
+            .is_some_and(|first_desync| first_desync <= tick)
+        {
+            return None;
+        }
+
+        self.newest_tick = self.newest_tick.max(tick);
+        let oldest_kept = Tick(self.newest_tick.0.saturating_sub(CHECKSUM_WINDOW_TICKS));
+        if tick < oldest_kept {
+            return None;
+        }
+        self.checksums = self.checksums.split_off(&oldest_kept);
+
+        let checksums = self.checksums.entry(tick).or_default();
+        checksums.insert(connection.clone(), checksum);
+
+        if checksums.values().any(|other| *other != checksum) {
+            self.first_desync = Some(tick);
+            let mut checksums: Vec<_> = checksums
+                .iter()
+                .map(|(connection, checksum)| (connection.socket_addr(), *checksum))
+                .collect();
+            checksums.sort_by_key(|(socket_addr, _)| *socket_addr);
+            return Some(DesyncReport { tick, checksums });
+        }
+
+        if checksums.len() >= peer_count {
+            self.checksums.remove(&tick);
+        }
+        None
+    }
+
+    pub fn remove_connection(&mut self, connection: &Connection) {
+        for checksums in self.checksums.values_mut() {
+            checksums.remove(connection);
+        }
+        self.checksums.retain(|_, checksums| !checksums.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn checksum_packet(tick: u64) -> ChecksumPacket {
+        ChecksumPacket {
+            tick: Tick(tick),
+            checksum: StateChecksum(tick),
+        }
+    }
+
+    #[test]
+    fn checksums_of_unreported_ticks_are_pruned() {
+        let mut detector = DesyncDetector::default();
+        let connection = Connection::new(SocketAddr::from(([127, 0, 0, 1], 1)));
+
+        for tick in 0..=CHECKSUM_WINDOW_TICKS * 2 {
+            assert!(detector
+                .add_checksum(&connection, &checksum_packet(tick), 2)
+                .is_none());
+        }
+
+        assert_eq!(detector.checksums.len() as u64, CHECKSUM_WINDOW_TICKS + 1);
+        assert_eq!(
+            detector.checksums.keys().next(),
+            Some(&Tick(CHECKSUM_WINDOW_TICKS))
+        );
+        assert!(detector
+            .add_checksum(&connection, &checksum_packet(0), 2)
+            .is_none());
+        assert!(!detector.checksums.contains_key(&Tick(0)));
+    }
+}
@@ -0,0 +1,9 @@
+mod action_validator;
+pub mod config;
+mod desync_detector;
+pub mod server;
+mod tick_scheduler;
+
+pub use config::{ServerConfig, StartPolicy};
+pub use cooltraption_simulation::validation::{ActionChecks, ActionRules, ValidationError};
+pub use server::Server;
@@ -1,67 +1,77 @@
-use std::sync::MutexGuard;
-use std::time::SystemTime;
-use std::time::UNIX_EPOCH;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
 
-use cooltraption_common::types::TimePoint;
-use cooltraption_network::builder::NodeEventHandlerBuilder;
-use cooltraption_network::client::*;
-use cooltraption_network::network_state::*;
-use cooltraption_network::packets::*;
-use cooltraption_simulation::ResetRequest;
-use cooltraption_simulation::SimulationPacket;
+use clap::Parser;
+use cooltraption_network::transport::Transport;
+use cooltraption_runtime::actions::action_checks;
+use cooltraption_server::{Server, ServerConfig, StartPolicy};
+use cooltraption_simulation::clock::TickRate;
+use log::LevelFilter;
 
-fn main() {
-    let handler1 =
-        |network_state_event: &NetworkStateEvent<SimulationPacket>,
-         locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>| {
-            if let NetworkStateEvent::Accepted(connection) = network_state_event {
-                let chat_msg = ChatMessage(String::from("Hello, this is a chat message"));
-                locked_network_state.send_packet(Packet::ChatMessage(chat_msg), connection);
-
-                let now_millis = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis();
-
-                let into_2_sec = now_millis % 2000;
-                let time_point = if into_2_sec > 1500 {
-                    TimePoint::from_millis(now_millis - into_2_sec + 4000)
-                } else {
-                    TimePoint::from_millis(now_millis - into_2_sec + 2000)
-                };
-
-                let reset_requeset = ResetRequest::AtTime(time_point);
-                for conn in locked_network_state.connections() {
-                    locked_network_state.send_packet(
-                        Packet::ClientPacket(SimulationPacket::ResetRequest(reset_requeset)),
-                        conn,
-                    )
-                }
-            }
+/// Dedicated server for cooltraption matches. Arguments override the values of the config file.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// YAML file with the server configuration
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    #[arg(short, long)]
+    bind: Option<SocketAddr>,
+    /// framed-tcp or web-socket
+    #[arg(long)]
+    transport: Option<Transport>,
+    /// Ticks per second
+    #[arg(long)]
+    tick_rate: Option<NonZeroU32>,
+    #[arg(long)]
+    input_delay: Option<u64>,
+    #[arg(long)]
+    max_players: Option<usize>,
+    #[arg(long, value_enum)]
+    start_policy: Option<StartPolicy>,
+    #[arg(long)]
+    log_level: Option<LevelFilter>,
+}
 
-            if let NetworkStateEvent::Message(connection, packet) = network_state_event {
-                match packet {
-                    Packet::ChatMessage(chat_message) => {
-                        locked_network_state
-                            .send_packet(Packet::ChatMessage(chat_message.clone()), connection);
-                    }
-                    Packet::ClientPacket(simulation_packet) => {
-                        for conn in locked_network_state
-                            .connections()
-                            .iter()
-                            .filter(|c| **c != connection)
-                        {
-                            locked_network_state
-                                .send_packet(Packet::ClientPacket(*simulation_packet), conn);
-                        }
-                    }
-                }
-            }
+impl Args {
+    fn into_config(self) -> anyhow::Result<ServerConfig> {
+        let mut config = match &self.config {
+            Some(path) => ServerConfig::load(path)?,
+            None => ServerConfig::default(),
         };
+        if let Some(bind) = self.bind {
+            config.bind_address = bind;
+        }
+        if let Some(transport) = self.transport {
+            config.transport = transport;
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            config.tick_rate = TickRate(tick_rate);
+        }
+        if let Some(input_delay) = self.input_delay {
+            config.input_delay = input_delay;
+        }
+        if let Some(max_players) = self.max_players {
+            config.max_players = max_players;
+        }
+        if let Some(start_policy) = self.start_policy {
+            config.start_policy = start_policy;
+        }
+        if let Some(log_level) = self.log_level {
+            config.log_level = log_level;
+        }
+        Ok(config)
+    }
+}
 
-    let mut builder = NodeEventHandlerBuilder::default();
-    builder.add_network_state_event_handler(Box::new(handler1));
-    let node_event_handler = builder.build();
+fn main() -> anyhow::Result<()> {
+    let config = Args::parse().into_config()?;
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .parse_default_env()
+        .init();
 
-    listen("0.0.0.0:5001", node_event_handler);
+    Server::bind(config, action_checks())?.run();
+    Ok(())
 }
@@ -0,0 +1,512 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::MutexGuard;
+
+use cooltraption_common::types::{PlayerId, TimePoint};
+use cooltraption_network::builder::NodeEventHandlerBuilder;
+use cooltraption_network::client::bind;
+use cooltraption_network::codec::BincodeCodec;
+use cooltraption_network::connection::Connection;
+use cooltraption_network::network_state::*;
+use cooltraption_network::packets::*;
+use cooltraption_network::reliability::Channel;
+use cooltraption_network::session::{Lobby, LobbyEvent};
+use cooltraption_simulation::action::ActionPacket;
+use cooltraption_simulation::state_transfer::{StateTransfer, TickSnapshot};
+use cooltraption_simulation::validation::ActionChecks;
+use cooltraption_simulation::ResetRequest;
+use cooltraption_simulation::SimulationPacket;
+use cooltraption_simulation::Tick;
+use log::{error, info, warn};
+use message_io::node::NodeHandler;
+
+use crate::action_validator::ActionValidator;
+use crate::config::{ServerConfig, StartPolicy};
+use crate::desync_detector::DesyncDetector;
+use crate::tick_scheduler::{ScheduleError, TickScheduler};
+
+pub struct Server {
+    node_event_handler: NodeEventHandler<SimulationPacket>,
+    local_addr: SocketAddr,
+}
+
+impl Server {
+    /// Binds the configured address without handling any connections until `run` is called.
+    /// Actions are validated by the checks the game registered for their type.
+    pub fn bind(config: ServerConfig, action_checks: ActionChecks) -> io::Result<Self> {
+        let mut builder = NodeEventHandlerBuilder::default();
+        builder.set_codec(BincodeCodec);
+        let mut session = Session::new(config.clone(), action_checks);
+        builder.add_network_state_event_handler(Box::new(
+            move |network_state_event: &NetworkStateEvent<SimulationPacket>,
+                  locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>| {
+                session.handle(network_state_event, locked_network_state)
+            },
+        ));
+        let node_event_handler = builder.build();
+
+        let local_addr = bind(config.bind_address, config.transport, &node_event_handler)?;
+        info!("Listening on {} via {}", local_addr, config.transport);
+        Ok(Self {
+            node_event_handler,
+            local_addr,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Calling `stop` on the handler makes `run` return
+    pub fn node_handler(&self) -> NodeHandler<Signal> {
+        self.node_event_handler.node_handler()
+    }
+
+    pub fn run(self) {
+        self.node_event_handler.handle_event_loop();
+    }
+}
+
+/// Simulation bookkeeping of a single room
+struct Match {
+    desync_detector: DesyncDetector,
+    tick_scheduler: TickScheduler,
+    started_at: TimePoint,
+    /// Scheduled actions of the last `action_log_ticks` ticks, oldest first
+    action_log: VecDeque<ActionPacket>,
+    /// Joined the running match and wait for a peer to provide a snapshot
+    pending_joiners: Vec<Connection>,
+    /// Peer that was asked for a snapshot and the tick it was asked for
+    snapshot_request: Option<(Connection, Tick)>,
+    /// Newest tick of the input batches of every player
+    received_up_to: HashMap<PlayerId, Tick>,
+}
+
+impl Match {
+    /// Schedules the action and sends it to every player of the room
+    fn schedule_action(
+        &mut self,
+        action_packet: &ActionPacket,
+        player: PlayerId,
+        action_log_ticks: u64,
+        room_connections: &[Connection],
+        network_state: &NetworkStateImpl<SimulationPacket>,
+    ) -> Result<(), ScheduleError> {
+        let scheduled_packet = self.tick_scheduler.schedule(action_packet, player)?;
+        self.log_action(scheduled_packet.clone(), action_log_ticks);
+        for conn in room_connections {
+            network_state.send_packet_on(
+                Packet::ClientPacket(SimulationPacket::ActionPacket(scheduled_packet.clone())),
+                conn,
+                Channel::ReliableUnordered,
+            );
+        }
+        Ok(())
+    }
+
+    fn log_action(&mut self, action_packet: ActionPacket, action_log_ticks: u64) {
+        let newest_tick = action_packet.tick;
+        self.action_log.push_back(action_packet);
+        while let Some(oldest) = self.action_log.front() {
+            if oldest.tick.0 + action_log_ticks >= newest_tick.0 {
+                break;
+            }
+            self.action_log.pop_front();
+        }
+    }
+
+    fn state_transfer(&self, snapshot: &TickSnapshot) -> StateTransfer {
+        StateTransfer {
+            snapshot: snapshot.clone(),
+            actions: self
+                .action_log
+                .iter()
+                .filter(|action_packet| action_packet.tick >= snapshot.tick)
+                .cloned()
+                .collect(),
+            match_started_at: self.started_at,
+        }
+    }
+}
+
+struct Session {
+    config: ServerConfig,
+    lobby: Lobby,
+    matches: HashMap<String, Match>,
+    action_validator: ActionValidator,
+}
+
+impl Session {
+    fn new(config: ServerConfig, action_checks: ActionChecks) -> Self {
+        Self {
+            lobby: Lobby::new(config.max_players),
+            matches: Default::default(),
+            action_validator: ActionValidator::new(config.action_rules.clone(), action_checks),
+            config,
+        }
+    }
+
+    fn handle(
+        &mut self,
+        network_state_event: &NetworkStateEvent<SimulationPacket>,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        match network_state_event {
+            NetworkStateEvent::Accepted(connection) => {
+                info!("{:?} connected", connection);
+                if let Some(greeting) = &self.config.greeting {
+                    locked_network_state.send_packet(
+                        Packet::ChatMessage(ChatMessage(greeting.clone())),
+                        connection,
+                    );
+                }
+            }
+            NetworkStateEvent::Disconnected(connection) => {
+                info!("{:?} disconnected", connection);
+                self.handle_disconnect(connection, locked_network_state);
+            }
+            NetworkStateEvent::Message(connection, packet) => {
+                self.handle_packet(connection, packet, locked_network_state)
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_disconnect(
+        &mut self,
+        connection: &Connection,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        if let Some(player) = self.lobby.player_id(connection) {
+            self.action_validator.remove_player(player);
+        }
+        let room = self.lobby.room_of(connection).map(str::to_string);
+        if let Some(room_match) = room.as_ref().and_then(|room| self.matches.get_mut(room)) {
+            room_match.desync_detector.remove_connection(connection);
+            room_match
+                .pending_joiners
+                .retain(|joiner| joiner != connection);
+            if room_match
+                .snapshot_request
+                .as_ref()
+                .is_some_and(|(peer, _)| peer == connection)
+            {
+                room_match.snapshot_request = None;
+            }
+        }
+        let lobby_event = self
+            .lobby
+            .handle_disconnect(connection, locked_network_state);
+        if let Some(lobby_event) = lobby_event {
+            self.handle_lobby_event(lobby_event, locked_network_state);
+        }
+        // The peer that was asked for a snapshot might have been the one that left
+        if let Some(room) = room {
+            self.request_snapshot(&room, locked_network_state);
+        }
+    }
+
+    /// Disconnecting a peer locally does not produce a disconnect event, so it is cleaned up here
+    fn kick(
+        &mut self,
+        connection: &Connection,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        warn!(
+            "Disconnecting {:?}: exceeded {} violations",
+            connection, self.config.action_rules.max_violations
+        );
+        locked_network_state.disconnect(connection.clone());
+        self.handle_disconnect(connection, locked_network_state);
+    }
+
+    fn handle_lobby_event(
+        &mut self,
+        lobby_event: LobbyEvent,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        match lobby_event {
+            LobbyEvent::Joined {
+                player_id, room, ..
+            } if self.matches.contains_key(&room) => {
+                let Some(joiner) = self.lobby.connection(player_id).cloned() else {
+                    return;
+                };
+                let room_match = self.matches.get_mut(&room).expect("match was checked");
+                if !room_match.pending_joiners.contains(&joiner) {
+                    room_match.pending_joiners.push(joiner);
+                }
+                self.request_snapshot(&room, locked_network_state);
+            }
+            LobbyEvent::Joined { room, .. } => {
+                let should_start = match self.config.start_policy {
+                    StartPolicy::OnJoin => true,
+                    StartPolicy::WhenFull => self.lobby.room_size(&room) == self.config.max_players,
+                    StartPolicy::ReadyCheck => false,
+                };
+                if should_start {
+                    self.start_match(&room, locked_network_state);
+                }
+            }
+            LobbyEvent::StartRequested(room) => self.start_match(&room, locked_network_state),
+            LobbyEvent::RoomClosed(room) => {
+                self.matches.remove(&room);
+            }
+            LobbyEvent::Disconnected { .. } | LobbyEvent::Left { .. } => {}
+        }
+    }
+
+    fn start_match(
+        &mut self,
+        room: &str,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        let now_millis = TimePoint::now().millis();
+        let into_2_sec = now_millis % 2000;
+        let time_point = if into_2_sec > 1500 {
+            TimePoint::from_millis(now_millis - into_2_sec + 4000)
+        } else {
+            TimePoint::from_millis(now_millis - into_2_sec + 2000)
+        };
+        info!(
+            "Starting match in room {} at {}ms",
+            room,
+            time_point.millis()
+        );
+
+        let mut tick_scheduler = TickScheduler::new(
+            self.config.tick_rate,
+            self.config.input_delay,
+            self.config.max_action_age,
+            self.config.max_action_lead,
+        );
+        tick_scheduler.start_at(time_point);
+        self.matches.insert(
+            room.to_string(),
+            Match {
+                desync_detector: DesyncDetector::default(),
+                tick_scheduler,
+                started_at: time_point,
+                action_log: Default::default(),
+                pending_joiners: vec![],
+                snapshot_request: None,
+                received_up_to: Default::default(),
+            },
+        );
+        self.lobby.reset_ready(room, locked_network_state);
+
+        let reset_request = ResetRequest::AtTime(time_point);
+        for conn in self.lobby.room_connections(room) {
+            locked_network_state.send_packet(
+                Packet::ClientPacket(SimulationPacket::ResetRequest(reset_request)),
+                &conn,
+            )
+        }
+    }
+
+    /// Asks a peer that is already part of the match for a snapshot for the pending joiners.
+    /// Without such a peer the match is restarted instead.
+    fn request_snapshot(
+        &mut self,
+        room: &str,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        let Some(room_match) = self.matches.get_mut(room) else {
+            return;
+        };
+        // The snapshot of the outstanding request is transferred to every pending joiner
+        if room_match.pending_joiners.is_empty() || room_match.snapshot_request.is_some() {
+            return;
+        }
+        let peer = self
+            .lobby
+            .room_connections(room)
+            .into_iter()
+            .find(|conn| !room_match.pending_joiners.contains(conn));
+        match peer {
+            Some(peer) => {
+                let tick = room_match.tick_scheduler.current_tick().unwrap_or_default();
+                locked_network_state.send_packet(
+                    Packet::ClientPacket(SimulationPacket::StateRequest(tick)),
+                    &peer,
+                );
+                room_match.snapshot_request = Some((peer, tick));
+            }
+            None => {
+                info!("No peer in room {} can provide a snapshot", room);
+                self.start_match(room, locked_network_state);
+            }
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        connection: &Connection,
+        packet: &Packet<SimulationPacket>,
+        locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>,
+    ) {
+        let simulation_packet = match packet {
+            Packet::ChatMessage(chat_message) => {
+                locked_network_state
+                    .send_packet(Packet::ChatMessage(chat_message.clone()), connection);
+                return;
+            }
+            Packet::Lobby(lobby_packet) => {
+                let lobby_event =
+                    self.lobby
+                        .handle_packet(connection, lobby_packet, locked_network_state);
+                if let Some(lobby_event) = lobby_event {
+                    self.handle_lobby_event(lobby_event, locked_network_state);
+                }
+                return;
+            }
+            Packet::TimeSync(_) => return,
+            Packet::ClientPacket(simulation_packet) => simulation_packet,
+        };
+
+        let (Some(player), Some(room)) = (
+            self.lobby.player_id(connection),
+            self.lobby.room_of(connection),
+        ) else {
+            warn!(
+                "Ignoring packet from {:?}, it did not join a room",
+                connection
+            );
+            return;
+        };
+        let room_connections = self.lobby.room_connections(room);
+        match simulation_packet {
+            SimulationPacket::ActionPacket(action_packet) => {
+                let Some(room_match) = self.matches.get_mut(room) else {
+                    warn!("Rejected action from {:?}: no match is running", connection);
+                    return;
+                };
+                if let Err(e) = self
+                    .action_validator
+                    .validate(player, &action_packet.action)
+                {
+                    warn!("Rejected action from {:?}: {}", connection, e);
+                } else if let Err(e) = room_match.schedule_action(
+                    action_packet,
+                    player,
+                    self.config.action_log_ticks,
+                    &room_connections,
+                    locked_network_state,
+                ) {
+                    if e.is_violation() {
+                        self.action_validator.add_violation(player);
+                    }
+                    warn!("Rejected action from {:?}: {}", connection, e);
+                }
+            }
+            SimulationPacket::InputBatch(input_batch) => {
+                let Some(room_match) = self.matches.get_mut(room) else {
+                    warn!("Rejected inputs from {:?}: no match is running", connection);
+                    return;
+                };
+                // Batches repeat actions until they are acknowledged, only new ones are scheduled
+                let received_up_to = room_match.received_up_to.get(&player).copied();
+                for action_packet in input_batch.actions.iter().filter(|action_packet| {
+                    received_up_to.is_none_or(|tick| action_packet.tick > tick)
+                }) {
+                    if let Err(e) = self
+                        .action_validator
+                        .validate(player, &action_packet.action)
+                    {
+                        warn!("Rejected action from {:?}: {}", connection, e);
+                    } else if let Err(e) = room_match.schedule_action(
+                        action_packet,
+                        player,
+                        self.config.action_log_ticks,
+                        &room_connections,
+                        locked_network_state,
+                    ) {
+                        if e.is_violation() {
+                            self.action_validator.add_violation(player);
+                        }
+                        warn!("Rejected action from {:?}: {}", connection, e);
+                    }
+                }
+                // A batch reaching too far ahead would swallow the real inputs of the player
+                let mut batch_up_to = input_batch.up_to;
+                if let Some(latest_tick) = room_match.tick_scheduler.latest_tick() {
+                    if batch_up_to > latest_tick {
+                        warn!(
+                            "Inputs of {:?} reach tick {}, beyond tick {}",
+                            connection, batch_up_to.0, latest_tick.0
+                        );
+                        self.action_validator.add_violation(player);
+                        batch_up_to = latest_tick;
+                    }
+                }
+                let up_to = received_up_to.map_or(batch_up_to, |tick| tick.max(batch_up_to));
+                room_match.received_up_to.insert(player, up_to);
+                locked_network_state.send_packet_on(
+                    Packet::ClientPacket(SimulationPacket::InputAck(up_to)),
+                    connection,
+                    Channel::UnreliableSequenced,
+                );
+            }
+            SimulationPacket::Checksum(checksum_packet) => {
+                let Some(room_match) = self.matches.get_mut(room) else {
+                    return;
+                };
+                if let Some(desync_report) = room_match.desync_detector.add_checksum(
+                    connection,
+                    checksum_packet,
+                    room_connections.len(),
+                ) {
+                    error!("Desync detected in room {}: {:?}", room, desync_report);
+                    for conn in &room_connections {
+                        locked_network_state.send_packet(
+                            Packet::ClientPacket(SimulationPacket::Desync(desync_report.clone())),
+                            conn,
+                        );
+                    }
+                }
+            }
+            SimulationPacket::StateSnapshot(tick_snapshot) => {
+                let requested = self.matches.get_mut(room).filter(|room_match| {
+                    room_match.snapshot_request.as_ref()
+                        == Some(&(connection.clone(), tick_snapshot.tick))
+                });
+                if let Some(room_match) = requested {
+                    room_match.snapshot_request = None;
+                    let state_transfer = room_match.state_transfer(tick_snapshot);
+                    for joiner in std::mem::take(&mut room_match.pending_joiners) {
+                        info!(
+                            "Transferring state of tick {} in room {} to {:?}",
+                            tick_snapshot.tick.0, room, joiner
+                        );
+                        locked_network_state.send_packet(
+                            Packet::ClientPacket(SimulationPacket::StateTransfer(
+                                state_transfer.clone(),
+                            )),
+                            &joiner,
+                        );
+                    }
+                } else {
+                    warn!(
+                        "{:?} sent a snapshot of tick {} that was not requested",
+                        connection, tick_snapshot.tick.0
+                    );
+                    self.action_validator.add_violation(player);
+                }
+            }
+            // Matches are only ever reset by the server
+            SimulationPacket::ResetRequest(_)
+            | SimulationPacket::Desync(_)
+            | SimulationPacket::InputAck(_)
+            | SimulationPacket::StateRequest(_)
+            | SimulationPacket::StateTransfer(_) => {
+                warn!("{:?} sent a packet only the server may send", connection);
+                self.action_validator.add_violation(player);
+            }
+        }
+
+        if self.action_validator.exceeded_violations(player) {
+            self.kick(connection, locked_network_state);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use cooltraption_common::types::{PlayerId, TimePoint};
+use cooltraption_simulation::action::ActionPacket;
+use cooltraption_simulation::clock::TickRate;
+use cooltraption_simulation::Tick;
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    NotStarted,
+    TooLate { issued_at: Tick, server_tick: Tick },
+    TooEarly { issued_at: Tick, server_tick: Tick },
+}
+
+impl ScheduleError {
+    /// Late actions are expected on bad connections, early ones are never sent by honest clients
+    pub fn is_violation(&self) -> bool {
+        matches!(self, ScheduleError::TooEarly { .. })
+    }
+}
+
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::NotStarted => write!(f, "the simulation has not started yet"),
+            ScheduleError::TooLate {
+                issued_at,
+                server_tick,
+            } => write!(
+                f,
+                "action issued at tick {} arrived at server tick {}",
+                issued_at.0, server_tick.0
+            ),
+            ScheduleError::TooEarly {
+                issued_at,
+                server_tick,
+            } => write!(
+                f,
+                "action issued for tick {} lies too far ahead of server tick {}",
+                issued_at.0, server_tick.0
+            ),
+        }
+    }
+}
+
+/// Owns the tick clock of a session and assigns every incoming action its execution tick
+pub struct TickScheduler {
+    tick_rate: TickRate,
+    input_delay: u64,
+    max_action_age: u64,
+    max_action_lead: u64,
+    start: Option<TimePoint>,
+    next_sequences: HashMap<PlayerId, u64>,
+}
+
+impl TickScheduler {
+    pub fn new(
+        tick_rate: TickRate,
+        input_delay: u64,
+        max_action_age: u64,
+        max_action_lead: u64,
+    ) -> Self {
+        Self {
+            tick_rate,
+            input_delay,
+            max_action_age,
+            max_action_lead,
+            start: None,
+            next_sequences: Default::default(),
+        }
+    }
+
+    /// Tick 0 starts at `time_point`, the same moment the clients reset their simulation
+    pub fn start_at(&mut self, time_point: TimePoint) {
+        self.start = Some(time_point);
+    }
+
+    pub fn current_tick(&self) -> Option<Tick> {
+        let start_millis = self.start?.millis();
+        let elapsed_millis = TimePoint::now().millis().checked_sub(start_millis)?;
+        let tick_nanos = self.tick_rate.tick_duration().as_nanos();
+        Some(Tick((elapsed_millis * 1_000_000 / tick_nanos) as u64))
+    }
+
+    /// Latest tick that actions may be issued for right now
+    pub fn latest_tick(&self) -> Option<Tick> {
+        let server_tick = self.current_tick()?;
+        Some(Tick(
+            server_tick
+                .0
+                .saturating_add(self.input_delay)
+                .saturating_add(self.max_action_lead),
+        ))
+    }
+
+    /// Stamps the action with the tick it was issued for, but at least the current server tick
+    /// plus the input delay, and the player that sent it. Actions that were issued for a tick
+    /// more than `max_action_age` ticks ago or after `latest_tick` are rejected. Every scheduled
+    /// action of a player gets the next sequence number of the player, which orders the actions
+    /// of a tick.
+    pub fn schedule(
+        &mut self,
+        action_packet: &ActionPacket,
+        player: PlayerId,
+    ) -> Result<ActionPacket, ScheduleError> {
+        let server_tick = self.current_tick().ok_or(ScheduleError::NotStarted)?;
+        if action_packet.tick.0 + self.max_action_age < server_tick.0 {
+            return Err(ScheduleError::TooLate {
+                issued_at: action_packet.tick,
+                server_tick,
+            });
+        }
+        if self
+            .latest_tick()
+            .is_some_and(|latest_tick| action_packet.tick > latest_tick)
+        {
+            return Err(ScheduleError::TooEarly {
+                issued_at: action_packet.tick,
+                server_tick,
+            });
+        }
+        let next_sequence = self.next_sequences.entry(player).or_default();
+        let sequence = *next_sequence;
+        *next_sequence += 1;
+        Ok(ActionPacket::new(
+            action_packet
+                .tick
+                .max(Tick(server_tick.0 + self.input_delay)),
+            player,
+            sequence,
+            action_packet.action.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cooltraption_simulation::action::Action;
+
+    /// Scheduler at 10 ticks per second that is 50ms into tick 100
+    fn scheduler() -> TickScheduler {
+        let mut tick_scheduler = TickScheduler::new(TickRate::new(10).unwrap(), 3, 30, 30);
+        tick_scheduler.start_at(TimePoint::from_millis(TimePoint::now().millis() - 10_050));
+        tick_scheduler
+    }
+
+    fn action_packet(tick: u64) -> ActionPacket {
+        let action = Action {
+            name: String::from("noop"),
+            version: 0,
+            payload: vec![],
+        };
+        ActionPacket::new(Tick(tick), PlayerId(0), 0, action)
+    }
+
+    #[test]
+    fn actions_are_scheduled_after_the_input_delay() {
+        let mut tick_scheduler = scheduler();
+        let server_tick = tick_scheduler.current_tick().unwrap();
+
+        let late = tick_scheduler
+            .schedule(&action_packet(server_tick.0 - 10), PlayerId(0))
+            .unwrap();
+        assert_eq!(late.tick, Tick(server_tick.0 + 3));
+
+        let ahead = tick_scheduler
+            .schedule(&action_packet(server_tick.0 + 20), PlayerId(0))
+            .unwrap();
+        assert_eq!(ahead.tick, Tick(server_tick.0 + 20));
+    }
+
+    #[test]
+    fn actions_outside_the_accepted_ticks_are_rejected() {
+        let mut tick_scheduler = scheduler();
+        let server_tick = tick_scheduler.current_tick().unwrap();
+
+        let too_late = tick_scheduler
+            .schedule(&action_packet(server_tick.0 - 40), PlayerId(0))
+            .unwrap_err();
+        assert!(matches!(too_late, ScheduleError::TooLate { .. }));
+        assert!(!too_late.is_violation());
+
+        let too_early = tick_scheduler
+            .schedule(&action_packet(server_tick.0 + 40), PlayerId(0))
+            .unwrap_err();
+        assert!(matches!(too_early, ScheduleError::TooEarly { .. }));
+        assert!(too_early.is_violation());
+
+        let mut not_started = TickScheduler::new(TickRate::default(), 3, 30, 30);
+        assert!(matches!(
+            not_started.schedule(&action_packet(0), PlayerId(0)),
+            Err(ScheduleError::NotStarted)
+        ));
+    }
+
+    #[test]
+    fn every_player_counts_its_scheduled_actions() {
+        let mut tick_scheduler = scheduler();
+        let server_tick = tick_scheduler.current_tick().unwrap();
+        let mut schedule = |tick: u64, player: PlayerId| {
+            tick_scheduler
+                .schedule(&action_packet(tick), player)
+                .map(|action_packet| (action_packet.player, action_packet.sequence))
+        };
+
+        assert_eq!(
+            schedule(server_tick.0, PlayerId(0)).unwrap(),
+            (PlayerId(0), 0)
+        );
+        assert!(schedule(server_tick.0 + 40, PlayerId(0)).is_err());
+        assert_eq!(
+            schedule(server_tick.0, PlayerId(0)).unwrap(),
+            (PlayerId(0), 1)
+        );
+        assert_eq!(
+            schedule(server_tick.0, PlayerId(1)).unwrap(),
+            (PlayerId(1), 0)
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cooltraption_network::builder::NodeEventHandlerBuilder;
+use cooltraption_network::client::{connect, ReconnectPolicy};
+use cooltraption_network::codec::BincodeCodec;
+use cooltraption_network::network_state::{NetworkStateEvent, NetworkStateImpl, Signal};
+use cooltraption_network::packets::Packet;
+use cooltraption_network::session::{LobbyPacket, SessionClient};
+use cooltraption_network::transport::Transport;
+use cooltraption_server::{ActionChecks, Server, ServerConfig, StartPolicy};
+use cooltraption_simulation::SimulationPacket;
+use message_io::node::NodeHandler;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Observed {
+    Joined,
+    MatchStarted,
+}
+
+/// Runs the server on an ephemeral port of the loopback interface
+fn start_server(start_policy: StartPolicy) -> (SocketAddr, NodeHandler<Signal>) {
+    let config = ServerConfig {
+        bind_address: SocketAddr::from(([127, 0, 0, 1], 0)),
+        start_policy,
+        ..Default::default()
+    };
+    let server = Server::bind(config, ActionChecks::default()).unwrap();
+    let address = server.local_addr();
+    let node_handler = server.node_handler();
+    thread::spawn(move || server.run());
+    (address, node_handler)
+}
+
+/// Joins the room and marks the player ready, as host it starts the match once everyone is ready
+fn start_client(address: SocketAddr, observed: Sender<Observed>) -> NodeHandler<Signal> {
+    let mut builder = NodeEventHandlerBuilder::default();
+    builder.set_codec(BincodeCodec);
+    let mut session_client = SessionClient::new("player", "room");
+    builder.add_network_state_event_handler(Box::new(
+        move |event: &NetworkStateEvent<SimulationPacket>,
+              locked_network_state: &mut MutexGuard<NetworkStateImpl<SimulationPacket>>| {
+            let reply = match event {
+                NetworkStateEvent::Connected(connection) => {
+                    Some((connection, session_client.join_packet()))
+                }
+                NetworkStateEvent::Message(connection, Packet::Lobby(lobby_packet)) => {
+                    session_client.handle(lobby_packet);
+                    match lobby_packet {
+                        LobbyPacket::Welcome { .. } => {
+                            let _ = observed.send(Observed::Joined);
+                            Some((connection, session_client.ready_packet(true)))
+                        }
+                        LobbyPacket::RoomState(_) if session_client.can_start_match() => {
+                            Some((connection, session_client.start_match_packet()))
+                        }
+                        _ => None,
+                    }
+                }
+                NetworkStateEvent::Message(
+                    _,
+                    Packet::ClientPacket(SimulationPacket::ResetRequest(_)),
+                ) => {
+                    let _ = observed.send(Observed::MatchStarted);
+                    None
+                }
+                _ => None,
+            };
+            if let Some((connection, lobby_packet)) = reply {
+                locked_network_state.send_packet(Packet::Lobby(lobby_packet), connection);
+            }
+        },
+    ));
+    let node_event_handler = builder.build();
+    let node_handler = node_event_handler.node_handler();
+    thread::spawn(move || {
+        connect(
+            address,
+            Transport::FramedTcp,
+            ReconnectPolicy::Never,
+            node_event_handler,
+        )
+        .unwrap()
+    });
+    node_handler
+}
+
+fn wait_for(observed: &Receiver<Observed>, expected: Observed) {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match observed.recv_timeout(timeout) {
+            Ok(event) if event == expected => return,
+            Ok(_) => {}
+            Err(e) => panic!("Client did not observe {:?}: {}", expected, e),
+        }
+    }
+}
+
+fn joins_and_starts(start_policy: StartPolicy) {
+    let (address, server) = start_server(start_policy);
+    let (observed_sender, observed) = channel();
+    let client = start_client(address, observed_sender);
+
+    wait_for(&observed, Observed::Joined);
+    wait_for(&observed, Observed::MatchStarted);
+
+    client.stop();
+    server.stop();
+}
+
+#[test]
+fn match_starts_once_a_player_joined() {
+    joins_and_starts(StartPolicy::OnJoin);
+}
+
+#[test]
+fn match_starts_once_the_ready_host_requests_it() {
+    joins_and_starts(StartPolicy::ReadyCheck);
+}
@@ -0,0 +1,88 @@
+//! Compares radius queries of the `SpatialHash` with a linear scan over every entity.
+//! Run with `cargo bench -p cooltraption_simulation --bench spatial_hash`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use cooltraption_simulation::system_sets::physics_set::{Float, FromNum2, Vec2f};
+use cooltraption_simulation::system_sets::spatial_set::SpatialHash;
+use cooltraption_simulation::{Entity, NetId};
+
+const QUERIES: u64 = 1_000;
+
+/// Balls per square unit, stays the same for every entity count
+const DENSITY: f64 = 0.25;
+
+/// Deterministic positions, so every run measures the same layout
+fn positions(count: u32) -> Vec<(Entity, Option<NetId>, Vec2f)> {
+    let side = (count as f64 / DENSITY).sqrt() as u64;
+    let mut state: u64 = 0x9e3779b97f4a7c15;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) % side
+    };
+    (0..count)
+        .map(|index| {
+            (
+                Entity::from_raw(index),
+                Some(NetId(index as u64)),
+                Vec2f::from_num(next(), next()),
+            )
+        })
+        .collect()
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn main() {
+    let radius = Float::from_num(4);
+    println!(
+        "{:>8} {:>12} {:>14} {:>14}",
+        "balls", "rebuild", "hash query", "linear query"
+    );
+    for count in [1_000, 10_000, 50_000, 100_000] {
+        let entities = positions(count);
+        let mut spatial_hash = SpatialHash::default();
+        let rebuild = time(|| spatial_hash.rebuild(entities.iter().copied()));
+
+        let centers: Vec<Vec2f> = entities
+            .iter()
+            .step_by(entities.len() / QUERIES as usize)
+            .take(QUERIES as usize)
+            .map(|(_, _, position)| *position)
+            .collect();
+        let hash_queries = time(|| {
+            for center in &centers {
+                black_box(spatial_hash.within_radius(*center, radius));
+            }
+        });
+        let linear_queries = time(|| {
+            for center in &centers {
+                black_box(
+                    entities
+                        .iter()
+                        .filter(|(_, _, position)| {
+                            let offset = position - center;
+                            offset.dot(&offset) <= radius * radius
+                        })
+                        .map(|(entity, _, _)| *entity)
+                        .collect::<Vec<_>>(),
+                );
+            }
+        });
+
+        println!(
+            "{:>8} {:>12?} {:>14?} {:>14?}",
+            count,
+            rebuild,
+            hash_queries / QUERIES as u32,
+            linear_queries / QUERIES as u32
+        );
+    }
+}
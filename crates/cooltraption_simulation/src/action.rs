@@ -1,51 +1,174 @@
+use bevy_ecs::schedule::{IntoSystemConfig, IntoSystemSetConfig, Schedule, SystemSet};
 use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bincode::Options;
 
+use cooltraption_common::types::PlayerId;
 
-use crate::components::Position;
-use crate::system_sets::physics_set::Float;
-use crate::Tick;
+use crate::{Actions, Tick};
 
-use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
-
-#[derive(Clone, Copy)]
-pub enum ActionRequest {
-    SpawnBall { requested_position: (Float, Float) },
-}
-
-#[derive(Debug, Resource, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Resource, Clone, Serialize, Deserialize)]
 pub struct ActionPacket {
     pub tick: Tick,
+    pub player: PlayerId,
+    /// Counts the actions of the player, orders the actions of a tick
+    pub sequence: u64,
     pub action: Action,
 }
 
 impl ActionPacket {
-    pub fn new(tick: Tick, action: Action) -> Self {
-        Self { tick, action }
+    pub fn new(tick: Tick, player: PlayerId, sequence: u64, action: Action) -> Self {
+        Self {
+            tick,
+            player,
+            sequence,
+            action,
+        }
+    }
+
+    pub fn player_action(&self) -> PlayerAction {
+        PlayerAction {
+            player: self.player,
+            sequence: self.sequence,
+            action: self.action.clone(),
+        }
+    }
+}
+
+/// An action together with the player that issued it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerAction {
+    pub player: PlayerId,
+    pub sequence: u64,
+    pub action: Action,
+}
+
+impl PlayerAction {
+    /// Actions of a tick are executed in this order on every peer, no matter the order they
+    /// arrived in
+    pub fn order_key(&self) -> (PlayerId, u64) {
+        (self.player, self.sequence)
+    }
+}
+
+/// Action that can be registered with the simulation, game crates define their own
+pub trait ActionType: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Tags the action on the wire, has to be unique among the registered actions
+    const NAME: &'static str;
+    /// Has to be bumped whenever the serialized layout of the action changes
+    const VERSION: u16 = 0;
+
+    /// Decodes an action that was serialized by an older version, `None` drops the action
+    fn migrate(_version: u16, _payload: &[u8]) -> Option<Self> {
+        None
     }
 }
 
-#[derive(Resource, Clone, Debug, Copy, Serialize, Deserialize)]
-pub enum Action {
-    SpawnBall(SpawnBallAction),
-    OutwardForce(OutwardForceAction),
-    CircularForce(CircularForceAction),
+/// Serialized `ActionType` tagged with its name and version
+#[derive(Resource, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Action {
+    pub name: String,
+    pub version: u16,
+    pub payload: Vec<u8>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct SpawnBallAction {
-    pub position: Position,
+impl Action {
+    pub fn new<A: ActionType>(action: &A) -> Self {
+        Self {
+            name: A::NAME.to_string(),
+            version: A::VERSION,
+            payload: encoding()
+                .serialize(action)
+                .expect("action to be serializable"),
+        }
+    }
+
+    pub fn is<A: ActionType>(&self) -> bool {
+        self.name == A::NAME
+    }
+
+    /// Returns `None` if the action is of another type or its version cannot be decoded
+    pub fn decode<A: ActionType>(&self) -> Option<A> {
+        if !self.is::<A>() {
+            return None;
+        }
+        if self.version == A::VERSION {
+            encoding().deserialize(&self.payload).ok()
+        } else {
+            A::migrate(self.version, &self.payload)
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct CircularForceAction {
-    pub position: Position,
-    pub strength: Float,
+fn encoding() -> impl Options {
+    bincode::DefaultOptions::new()
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct OutwardForceAction {
-    pub position: Position,
-    pub strength: Float,
+/// Actions of type `A` that are executed in the current tick, ordered by `PlayerAction::order_key`
+#[derive(Resource)]
+pub struct ActionsOf<A: ActionType>(pub Vec<(PlayerId, A)>);
+
+/// Every action handler runs in this set
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct ActionSet;
+
+/// Orders the handlers by registration, so their effects are applied in the same order on
+/// every peer
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+struct ActionHandlerSet(usize);
+
+/// Names of the registered actions, in the order their handlers run in
+#[derive(Default)]
+pub struct ActionRegistry {
+    names: Vec<&'static str>,
 }
 
+impl ActionRegistry {
+    /// Adds a handler system that reads the `ActionsOf<A>` resource of the current tick
+    pub fn register<A: ActionType, M>(
+        &mut self,
+        schedule: &mut Schedule,
+        handler: impl IntoSystemConfig<M>,
+    ) -> &mut Self {
+        if self.names.contains(&A::NAME) {
+            panic!("Action {} was registered twice !!!", A::NAME);
+        }
+        let index = self.names.len();
+        self.names.push(A::NAME);
+
+        schedule.configure_set(ActionHandlerSet(index).in_set(ActionSet));
+        if let Some(previous) = index.checked_sub(1) {
+            schedule.configure_set(ActionHandlerSet(index).after(ActionHandlerSet(previous)));
+        }
+        schedule.add_system(decode_actions::<A>.in_set(ActionHandlerSet(index)));
+        schedule.add_system(
+            handler
+                .in_set(ActionHandlerSet(index))
+                .after(decode_actions::<A>),
+        );
+        self
+    }
+
+    pub fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+}
+
+fn decode_actions<A: ActionType>(world: &mut World) {
+    let actions: Vec<(PlayerId, A)> = world
+        .get_resource::<Actions>()
+        .map(|actions| {
+            actions
+                .0
+                .iter()
+                .filter_map(|PlayerAction { player, action, .. }| {
+                    action.decode::<A>().map(|action| (*player, action))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    world.insert_resource(ActionsOf(actions));
+}
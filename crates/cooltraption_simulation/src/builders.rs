@@ -1,7 +1,14 @@
 use super::*;
+use crate::system_sets::lifecycle_set::{EntityCap, WorldBounds};
+use serde::de::DeserializeOwned;
 
 pub type SimulationStateHandler = Box<dyn FnMut(&mut SimulationState) + Send>;
 pub type LocalActionPacketHandler = Box<dyn FnMut(&ActionPacket) + Send>;
+pub type ChecksumHandler = Box<dyn FnMut(&ChecksumPacket) + Send>;
+pub type SnapshotHandler = Box<dyn FnMut(&TickSnapshot) + Send>;
+pub type InputBatchHandler = Box<dyn FnMut(&InputBatch) + Send>;
+pub type StatsHandler = Box<dyn FnMut(&SimulationStats) + Send>;
+pub type ReplayHandler = Box<dyn FnMut(&Replay) + Send>;
 
 #[derive(Default)]
 pub struct SimulationRunOptionsBuilder {
@@ -24,6 +31,99 @@ impl SimulationRunOptionsBuilder {
         self
     }
 
+    /// Ticks whose snapshots are published to the snapshot callbacks, each once it is the oldest
+    /// tick of the rollback window
+    pub fn set_snapshot_requests(&mut self, snapshot_requests: BoxedIt<Tick>) -> &mut Self {
+        self.run_opts.snapshot_requests = snapshot_requests;
+        self
+    }
+
+    /// Snapshots of running matches that late joiners continue from
+    pub fn set_state_transfers(&mut self, state_transfers: BoxedIt<StateTransfer>) -> &mut Self {
+        self.run_opts.state_transfers = state_transfers;
+        self
+    }
+
+    pub fn set_stop_signal(&mut self, should_stop: BoxedGenerator<bool>) -> &mut Self {
+        self.run_opts.should_stop_generator = should_stop;
+        self
+    }
+
+    /// Clock that `ResetRequest::AtTime` is measured against, defaults to the local clock
+    pub fn set_synced_clock(&mut self, synced_clock: SyncedClock) -> &mut Self {
+        self.run_opts.synced_clock = synced_clock;
+        self
+    }
+
+    /// Disable if local actions are only executed once an authoritative server echoes them back
+    pub fn set_apply_local_actions(&mut self, apply_local_actions: bool) -> &mut Self {
+        self.run_opts.apply_local_actions = apply_local_actions;
+        self
+    }
+
+    /// Player that local actions are issued by, an authoritative server may overwrite it
+    pub fn set_local_player(&mut self, local_player: PlayerId) -> &mut Self {
+        self.run_opts.local_player = local_player;
+        self
+    }
+
+    pub fn set_input_delay(&mut self, input_delay: InputDelay) -> &mut Self {
+        self.run_opts.input_delay = input_delay;
+        self
+    }
+
+    /// Round trip time that an adaptive `InputDelay` is derived from
+    pub fn set_rtt_source(&mut self, rtt_source: BoxedGenerator<Option<Duration>>) -> &mut Self {
+        self.run_opts.rtt_source = rtt_source;
+        self
+    }
+
+    /// Ticks that unacknowledged local actions keep being resent for
+    pub fn set_input_redundancy(&mut self, redundancy: u64) -> &mut Self {
+        self.run_opts.input_buffer = InputBuffer::new(redundancy);
+        self
+    }
+
+    /// Ticks up to which the local actions were received by the other side
+    pub fn set_input_acks(&mut self, input_acks: BoxedIt<Tick>) -> &mut Self {
+        self.run_opts.input_acks = input_acks;
+        self
+    }
+
+    /// Records every executed tick, finished replays are handed to the replay callbacks
+    pub fn set_replay_recorder(&mut self, replay_recorder: ReplayRecorder) -> &mut Self {
+        self.run_opts.replay_recorder = Some(replay_recorder);
+        self
+    }
+
+    pub fn set_rollback_depth(&mut self, depth: usize) -> &mut Self {
+        self.run_opts.rollback_buffer = RollbackBuffer::new(depth);
+        self
+    }
+
+    pub fn set_clock_mode(&mut self, clock_mode: ClockMode) -> &mut Self {
+        self.run_opts.clock_mode = clock_mode;
+        self
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: TickRate) -> &mut Self {
+        self.run_opts.tick_rate = tick_rate;
+        self
+    }
+
+    /// Stops running once this tick was simulated
+    pub fn set_last_tick(&mut self, last_tick: Tick) -> &mut Self {
+        self.run_opts.last_tick = Some(last_tick);
+        self
+    }
+
+    /// Upper bound of ticks that are run back to back when the simulation fell behind.
+    /// If the simulation is further behind, the remaining ticks are delayed instead.
+    pub fn set_max_catch_up_ticks(&mut self, max_catch_up_ticks: u64) -> &mut Self {
+        self.run_opts.max_catch_up_ticks = max_catch_up_ticks;
+        self
+    }
+
     pub fn add_state_complete_callback(&mut self, handler: SimulationStateHandler) -> &mut Self {
         self.run_opts.state_complete_handler.push(handler);
         self
@@ -37,6 +137,35 @@ impl SimulationRunOptionsBuilder {
         self
     }
 
+    /// Called with the checksum of every tick that left the rollback window and is therefore final
+    pub fn add_checksum_callback(&mut self, handler: ChecksumHandler) -> &mut Self {
+        self.run_opts.checksum_callbacks.push(handler);
+        self
+    }
+
+    /// Called every tick with the local actions that were not acknowledged yet
+    pub fn add_input_batch_callback(&mut self, handler: InputBatchHandler) -> &mut Self {
+        self.run_opts.input_batch_callbacks.push(handler);
+        self
+    }
+
+    pub fn add_snapshot_callback(&mut self, handler: SnapshotHandler) -> &mut Self {
+        self.run_opts.snapshot_callbacks.push(handler);
+        self
+    }
+
+    pub fn add_stats_callback(&mut self, handler: StatsHandler) -> &mut Self {
+        self.run_opts.stats_callbacks.push(handler);
+        self
+    }
+
+    /// Called with the recorded replay whenever the simulation is reset, receives a state
+    /// transfer or stops
+    pub fn add_replay_callback(&mut self, handler: ReplayHandler) -> &mut Self {
+        self.run_opts.replay_callbacks.push(handler);
+        self
+    }
+
     pub fn build(self) -> SimulationRunConfig {
         self.run_opts
     }
@@ -45,6 +174,7 @@ impl SimulationRunOptionsBuilder {
 #[derive(Default)]
 pub struct SimulationImplBuilder {
     simulation: SimulationImpl,
+    action_registry: ActionRegistry,
 }
 
 impl SimulationImplBuilder {
@@ -54,9 +184,55 @@ impl SimulationImplBuilder {
 
     pub fn set_schedule(&mut self, schedule: Schedule) -> &mut Self {
         self.simulation.schedule = schedule;
+        self.action_registry = Default::default();
         self
     }
 
+    /// Runs `handler` whenever actions of type `A` are executed, handlers run in the order
+    /// they were registered in. Has to be called after the schedule was set.
+    pub fn register_action<A: ActionType, M>(
+        &mut self,
+        handler: impl IntoSystemConfig<M>,
+    ) -> &mut Self {
+        self.action_registry
+            .register::<A, M>(&mut self.simulation.schedule, handler);
+        self
+    }
+
+    pub fn register_snapshot_component<C: Component + Serialize + DeserializeOwned>(
+        &mut self,
+    ) -> &mut Self {
+        self.simulation
+            .simulation_state
+            .snapshot_registry_mut()
+            .register::<C>();
+        self
+    }
+
+    /// Entities outside of the bounds are confined according to the boundary mode
+    pub fn set_world_bounds(&mut self, world_bounds: WorldBounds) -> &mut Self {
+        self.simulation
+            .simulation_state
+            .set_world_bounds(world_bounds);
+        self
+    }
+
+    /// Limits how many entities the world holds at the end of every tick
+    pub fn set_entity_cap(&mut self, entity_cap: EntityCap) -> &mut Self {
+        self.simulation.simulation_state.set_entity_cap(entity_cap);
+        self
+    }
+
+    /// Starts the simulation from the snapshot instead of an empty world, has to be called
+    /// after every snapshot component was registered
+    pub fn restore_state(
+        &mut self,
+        snapshot: &SimulationStateSnapshot,
+    ) -> anyhow::Result<&mut Self> {
+        self.simulation.simulation_state.restore(snapshot)?;
+        Ok(self)
+    }
+
     pub fn build(self) -> SimulationImpl {
         self.simulation
     }
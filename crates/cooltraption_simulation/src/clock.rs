@@ -0,0 +1,62 @@
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Ticks per second
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TickRate(pub NonZeroU32);
+
+impl Default for TickRate {
+    fn default() -> Self {
+        Self(NonZeroU32::new(60).expect("60 to be non-zero"))
+    }
+}
+
+impl TickRate {
+    /// Returns `None` for a tick rate of 0
+    pub fn new(ticks_per_second: u32) -> Option<Self> {
+        NonZeroU32::new(ticks_per_second).map(Self)
+    }
+
+    pub fn ticks_per_second(&self) -> u32 {
+        self.0.get()
+    }
+
+    /// At least a nanosecond, even for tick rates above a billion
+    pub fn tick_duration(&self) -> Duration {
+        self.ticks_duration(1).max(Duration::from_nanos(1))
+    }
+
+    /// Duration of `ticks` ticks rounded down to the nanosecond, saturates instead of overflowing
+    pub fn ticks_duration(&self, ticks: u64) -> Duration {
+        let nanos = ticks as u128 * 1_000_000_000 / self.0.get() as u128;
+        Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMode {
+    /// Every tick advances by exactly one tick of the `TickRate`, which keeps peers in lockstep
+    #[default]
+    Fixed,
+    /// Every tick advances by the measured time since the last tick
+    WallClock,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_duration_does_not_accumulate_rounding_errors() {
+        let tick_rate = TickRate::default();
+        assert_eq!(tick_rate.tick_duration(), Duration::from_nanos(16_666_666));
+        assert_eq!(tick_rate.ticks_duration(60), Duration::from_secs(1));
+        assert_eq!(tick_rate.ticks_duration(90), Duration::from_millis(1500));
+        assert_eq!(
+            tick_rate.ticks_duration(u64::MAX),
+            Duration::from_nanos(u64::MAX)
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use derive_more::{Add, AddAssign, Deref, Div, From, Into, Mul, Neg, Sub};
 
 use bevy_ecs::prelude::*;
+use cooltraption_common::types::PlayerId;
 
 use crate::system_sets::physics_set::FromNum2;
-use crate::system_sets::physics_set::Vec2f;
+use crate::system_sets::physics_set::{Float, Vec2f};
+use crate::Tick;
 
 use serde::{Deserialize, Serialize};
 
@@ -29,13 +31,46 @@ impl Default for Acceleration {
     }
 }
 
+/// Mass of the entity, entities without a weight weigh 1
 #[rustfmt::skip]
-#[derive(Component, Default, Clone, Debug, Copy, Serialize, Deserialize, Deref, Add, Mul, Sub, Div, From, Into, AddAssign, Neg)]
-pub struct Weight(pub f64);
+#[derive(Component, Clone, Debug, Copy, Serialize, Deserialize, Deref, Add, Mul, Sub, Div, From, Into, AddAssign, Neg)]
+pub struct Weight(pub Float);
+impl Default for Weight {
+    fn default() -> Self {
+        Weight(Float::from_num(1))
+    }
+}
+
+impl Weight {
+    /// A weight of 0 or less is treated as infinitely heavy
+    pub fn inverse(&self) -> Float {
+        if self.0 > Float::from_num(0) {
+            Float::from_num(1) / self.0
+        } else {
+            Float::from_num(0)
+        }
+    }
+}
+
+/// Sum of the forces acting on the entity during the current tick, cleared after every movement
+#[rustfmt::skip]
+#[derive(Component, Clone, Debug, Copy, Serialize, Deserialize, Deref, Add, Mul, Sub, Div, From, Into, AddAssign, Neg)]
+pub struct Force(pub Vec2f);
+impl Default for Force {
+    fn default() -> Self {
+        Force(Vec2f::from_num(0, 0))
+    }
+}
 
+/// Share of the velocity that is lost per second
 #[rustfmt::skip]
-#[derive(Component, Default, Clone, Debug, Copy, Serialize, Deserialize, Deref, Add, Mul, Sub, Div, From, Into, AddAssign, Neg)]
-pub struct Force(pub f64);
+#[derive(Component, Clone, Debug, Copy, Serialize, Deserialize, Deref, Add, Mul, Sub, Div, From, Into, AddAssign, Neg)]
+pub struct Drag(pub Float);
+impl Default for Drag {
+    fn default() -> Self {
+        Drag(Float::from_num(0))
+    }
+}
 
 #[rustfmt::skip]
 #[derive(Component, Default, Clone, Debug, Serialize, Deserialize, Deref, From, Into)]
@@ -43,10 +78,53 @@ pub struct Drawable {
     pub asset: String,
 }
 
+/// The player whose action spawned the entity
+#[rustfmt::skip]
+#[derive(Component, Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Deref)]
+pub struct Owner(pub PlayerId);
+
+/// Ticks the entity has left to live, it is despawned once none are left
+#[rustfmt::skip]
+#[derive(Component, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize, Deref)]
+pub struct Lifetime(pub u64);
+
+/// Tick the entity was spawned in, entities without one count as spawned in the first tick
+#[rustfmt::skip]
+#[derive(Component, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize, Deref)]
+pub struct SpawnedAt(pub Tick);
+
+/// Identifies the entity the same way on every peer, restoring a snapshot does not restore
+/// the `Entity` ids. Entities are ordered by it wherever the order affects the simulation.
+#[rustfmt::skip]
+#[derive(Component, Clone, Debug, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Deref)]
+pub struct NetId(pub u64);
+
+/// Hands out the net ids, saved in every snapshot
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetIds {
+    next: u64,
+}
+
+impl NetIds {
+    pub fn allocate(&mut self) -> NetId {
+        let net_id = NetId(self.next);
+        self.next += 1;
+        net_id
+    }
+}
+
+/// Sort key that is the same on every peer, entities without a `NetId` come last
+pub fn entity_order(entity: Entity, net_id: Option<&NetId>) -> (u64, u64) {
+    (net_id.map_or(u64::MAX, |net_id| net_id.0), entity.to_bits())
+}
+
 #[rustfmt::skip]
 #[derive(Bundle)]
 pub struct PhysicsBundle {
     pub acc: Acceleration,
     pub vel: Velocity,
     pub pos: Position,
+    pub weight: Weight,
+    pub force: Force,
+    pub drag: Drag,
 }
@@ -0,0 +1,19 @@
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::snapshot::StateChecksum;
+use crate::Tick;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumPacket {
+    pub tick: Tick,
+    pub checksum: StateChecksum,
+}
+
+/// Sent by the server when peers reported different checksums for the same tick
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DesyncReport {
+    pub tick: Tick,
+    pub checksums: Vec<(SocketAddr, StateChecksum)>,
+}
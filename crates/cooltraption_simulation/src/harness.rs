@@ -0,0 +1,96 @@
+use std::iter;
+use std::sync::mpsc::{channel, Sender};
+
+use cooltraption_common::types::PlayerId;
+
+use crate::action::{Action, ActionPacket};
+use crate::builders::SimulationRunOptionsBuilder;
+use crate::simulation_state::SimulationState;
+use crate::snapshot::{SimulationStateSnapshot, StateChecksum};
+use crate::system_sets::physics_set::DeltaTime;
+use crate::{Schedule, SimulationImpl, SimulationRunConfig, Tick};
+
+/// Runs a `SimulationImpl` without a clock, every call to `advance` runs the ticks instantly.
+/// Every tick advances by the fixed `DeltaTime` of the configured `TickRate`.
+pub struct SimulationHarness {
+    simulation: SimulationImpl,
+    run_options: SimulationRunConfig,
+    action_packet_sender: Sender<ActionPacket>,
+    next_sequence: u64,
+}
+
+impl SimulationHarness {
+    pub fn new(schedule: Schedule) -> Self {
+        Self::with_run_options(schedule, SimulationRunOptionsBuilder::default())
+    }
+
+    pub fn with_run_options(
+        schedule: Schedule,
+        mut run_options_builder: SimulationRunOptionsBuilder,
+    ) -> Self {
+        let (action_packet_sender, action_packet_receiver) = channel::<ActionPacket>();
+        run_options_builder.set_action_packets(Box::new(iter::from_fn(move || {
+            action_packet_receiver.try_recv().ok()
+        })));
+
+        Self {
+            simulation: SimulationImpl::new(SimulationState::default(), schedule),
+            run_options: run_options_builder.build(),
+            action_packet_sender,
+            next_sequence: 0,
+        }
+    }
+
+    /// Packets for ticks that already ran are rolled back like late network packets
+    pub fn push_action_packet(&mut self, action_packet: ActionPacket) -> &mut Self {
+        self.action_packet_sender
+            .send(action_packet)
+            .expect("harness to own the receiver");
+        self
+    }
+
+    pub fn push_action(&mut self, tick: Tick, player: PlayerId, action: Action) -> &mut Self {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.push_action_packet(ActionPacket::new(tick, player, sequence, action))
+    }
+
+    pub fn advance(&mut self, ticks: u64) -> &mut Self {
+        let dt = DeltaTime::from(self.run_options.tick_rate);
+        for _ in 0..ticks {
+            self.simulation.tick(&mut self.run_options, dt);
+        }
+        self
+    }
+
+    pub fn advance_to(&mut self, tick: Tick) -> &mut Self {
+        let ticks = tick.0.saturating_sub(self.current_tick().0);
+        self.advance(ticks)
+    }
+
+    /// Hands the replay recorded so far to the replay callbacks
+    pub fn finish_replay(&mut self) -> &mut Self {
+        SimulationImpl::finish_replay(&mut self.run_options);
+        self
+    }
+
+    pub fn current_tick(&self) -> Tick {
+        self.simulation.state().current_tick()
+    }
+
+    pub fn state(&self) -> &SimulationState {
+        self.simulation.state()
+    }
+
+    pub fn state_mut(&mut self) -> &mut SimulationState {
+        &mut self.simulation.simulation_state
+    }
+
+    pub fn snapshot(&self) -> SimulationStateSnapshot {
+        self.state().snapshot()
+    }
+
+    pub fn checksum(&self) -> StateChecksum {
+        self.state().checksum()
+    }
+}
@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::action::ActionPacket;
+use crate::clock::TickRate;
+use crate::Tick;
+
+/// Ticks that local actions are scheduled ahead, so they reach remote peers before they execute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDelay {
+    Fixed(u64),
+    /// Covers half the measured round trip time plus `margin` ticks, but never exceeds `max`
+    Adaptive {
+        margin: u64,
+        max: u64,
+    },
+}
+
+impl Default for InputDelay {
+    fn default() -> Self {
+        Self::Fixed(0)
+    }
+}
+
+impl InputDelay {
+    pub fn ticks(&self, rtt: Option<Duration>, tick_rate: TickRate) -> u64 {
+        match *self {
+            InputDelay::Fixed(ticks) => ticks,
+            InputDelay::Adaptive { margin, max } => {
+                let one_way_nanos = rtt.unwrap_or_default().as_nanos() / 2;
+                let tick_nanos = tick_rate.tick_duration().as_nanos();
+                let one_way_ticks = one_way_nanos.div_ceil(tick_nanos) as u64;
+                (one_way_ticks + margin).min(max)
+            }
+        }
+    }
+}
+
+/// Every local action up to tick `up_to` that was not acknowledged yet.
+/// Receiving a batch makes all actions up to `up_to` known, later batches repeat lost ones.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputBatch {
+    pub up_to: Tick,
+    pub actions: Vec<ActionPacket>,
+}
+
+/// Local actions that are resent until they are acknowledged
+#[derive(Debug, Clone)]
+pub struct InputBuffer {
+    redundancy: u64,
+    sent_up_to: Option<Tick>,
+    unacked: Vec<ActionPacket>,
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+impl InputBuffer {
+    /// Actions are given up on once they are `redundancy` ticks older than the newest action
+    pub fn new(redundancy: u64) -> Self {
+        Self {
+            redundancy,
+            sent_up_to: None,
+            unacked: vec![],
+        }
+    }
+
+    /// Tick for actions issued in `current_tick`, always later than every batched action so that
+    /// a lower input delay does not mix new actions into ticks that were already acknowledged
+    pub fn next_tick(&self, current_tick: Tick, input_delay: u64) -> Tick {
+        let delayed_tick = Tick(current_tick.0 + input_delay);
+        match self.sent_up_to {
+            Some(sent_up_to) if sent_up_to >= delayed_tick => Tick(sent_up_to.0 + 1),
+            _ => delayed_tick,
+        }
+    }
+
+    pub fn push(&mut self, action_packet: ActionPacket) {
+        self.sent_up_to = Some(match self.sent_up_to {
+            Some(sent_up_to) => sent_up_to.max(action_packet.tick),
+            None => action_packet.tick,
+        });
+        self.unacked.push(action_packet);
+    }
+
+    pub fn acknowledge(&mut self, up_to: Tick) {
+        self.unacked
+            .retain(|action_packet| action_packet.tick > up_to);
+    }
+
+    /// Returns `None` if every action was acknowledged. Actions that are `redundancy` ticks
+    /// older than the newest action are no longer resent.
+    pub fn batch(&mut self) -> Option<InputBatch> {
+        let up_to = self.sent_up_to?;
+        let oldest_tick = up_to.0.saturating_sub(self.redundancy);
+        let unacked_count = self.unacked.len();
+        self.unacked
+            .retain(|action_packet| action_packet.tick.0 >= oldest_tick);
+        if self.unacked.len() < unacked_count {
+            debug!(
+                "Stopped resending {} actions that were not acknowledged yet",
+                unacked_count - self.unacked.len()
+            );
+        }
+
+        (!self.unacked.is_empty()).then(|| InputBatch {
+            up_to,
+            actions: self.unacked.clone(),
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.sent_up_to = None;
+        self.unacked.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use cooltraption_common::types::PlayerId;
+
+    fn action_packet(tick: u64) -> ActionPacket {
+        let action = Action {
+            name: String::from("noop"),
+            version: 0,
+            payload: vec![],
+        };
+        ActionPacket::new(Tick(tick), PlayerId(0), tick, action)
+    }
+
+    fn batched_ticks(input_buffer: &mut InputBuffer) -> Option<(Tick, Vec<Tick>)> {
+        input_buffer.batch().map(|input_batch| {
+            let ticks = input_batch
+                .actions
+                .iter()
+                .map(|action_packet| action_packet.tick)
+                .collect();
+            (input_batch.up_to, ticks)
+        })
+    }
+
+    #[test]
+    fn batches_repeat_actions_until_they_are_acknowledged() {
+        let mut input_buffer = InputBuffer::new(8);
+        assert!(input_buffer.batch().is_none());
+
+        input_buffer.push(action_packet(3));
+        input_buffer.push(action_packet(5));
+        assert_eq!(
+            batched_ticks(&mut input_buffer),
+            Some((Tick(5), vec![Tick(3), Tick(5)]))
+        );
+        assert_eq!(
+            batched_ticks(&mut input_buffer),
+            Some((Tick(5), vec![Tick(3), Tick(5)]))
+        );
+
+        input_buffer.acknowledge(Tick(3));
+        assert_eq!(
+            batched_ticks(&mut input_buffer),
+            Some((Tick(5), vec![Tick(5)]))
+        );
+        input_buffer.acknowledge(Tick(5));
+        assert!(input_buffer.batch().is_none());
+    }
+
+    #[test]
+    fn actions_older_than_the_redundancy_are_given_up_on() {
+        let mut input_buffer = InputBuffer::new(8);
+        input_buffer.push(action_packet(1));
+        input_buffer.push(action_packet(9));
+        input_buffer.push(action_packet(10));
+        assert_eq!(
+            batched_ticks(&mut input_buffer),
+            Some((Tick(10), vec![Tick(9), Tick(10)]))
+        );
+    }
+
+    #[test]
+    fn new_actions_are_scheduled_after_every_sent_action() {
+        let mut input_buffer = InputBuffer::default();
+        assert_eq!(input_buffer.next_tick(Tick(10), 3), Tick(13));
+        input_buffer.push(action_packet(13));
+        // A lower input delay must not reach into ticks that were already sent
+        assert_eq!(input_buffer.next_tick(Tick(11), 1), Tick(14));
+        assert_eq!(input_buffer.next_tick(Tick(20), 1), Tick(21));
+    }
+
+    #[test]
+    fn adaptive_input_delay_covers_half_the_rtt() {
+        let tick_rate = TickRate::new(10).unwrap();
+        let adaptive = InputDelay::Adaptive { margin: 1, max: 5 };
+        assert_eq!(adaptive.ticks(None, tick_rate), 1);
+        assert_eq!(
+            adaptive.ticks(Some(Duration::from_millis(200)), tick_rate),
+            2
+        );
+        assert_eq!(
+            adaptive.ticks(Some(Duration::from_millis(250)), tick_rate),
+            3
+        );
+        assert_eq!(adaptive.ticks(Some(Duration::from_secs(10)), tick_rate), 5);
+        assert_eq!(
+            InputDelay::Fixed(4).ticks(Some(Duration::from_secs(10)), tick_rate),
+            4
+        );
+    }
+}
@@ -13,39 +13,70 @@ pub use bevy_ecs::schedule::Schedule;
 pub use bevy_ecs::system::Resource;
 pub use bevy_ecs::world::*;
 
-use action::{Action, ActionPacket};
-pub use components::{Acceleration, PhysicsBundle, Position, Velocity};
-use cooltraption_common::types::TimePoint;
+use action::{Action, ActionPacket, ActionRegistry, ActionType, PlayerAction};
+use clock::{ClockMode, TickRate};
+pub use components::{
+    Acceleration, Drag, Force, Lifetime, NetId, NetIds, Owner, PhysicsBundle, Position, SpawnedAt,
+    Velocity, Weight,
+};
+use cooltraption_common::types::{PlayerId, SyncedClock, TimePoint};
+use desync::{ChecksumPacket, DesyncReport};
+use input::{InputBatch, InputBuffer, InputDelay};
+use replay::{Replay, ReplayRecorder};
+use rollback::{HistoryEntry, RollbackBuffer};
 use simulation_state::SimulationState;
+use snapshot::SimulationStateSnapshot;
+use state_transfer::{StateTransfer, TickSnapshot};
+use stats::SimulationStats;
 use system_sets::physics_set;
+use system_sets::physics_set::DeltaTime;
 
 use derive_more::{Add, AddAssign, Deref, Div, From, Into, Mul, Sub};
-use log::error;
-use rsntp::SntpClient;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 
 use builders::*;
 
 pub mod action;
 pub mod builders;
+pub mod clock;
 pub mod components;
+pub mod desync;
+pub mod harness;
+pub mod input;
+pub mod replay;
+pub mod rollback;
 pub mod simulation_state;
+pub mod snapshot;
+pub mod state_transfer;
+pub mod stats;
 pub mod system_sets;
+pub mod validation;
 
 #[rustfmt::skip]
 #[derive(Debug, Resource, Clone, Default, Eq, Hash, PartialEq, Copy, Serialize, Deserialize, Deref, Add, Mul, Sub, Div, From, Into, AddAssign, PartialOrd, Ord)]
 pub struct Tick(pub u64);
 
+/// Actions that are executed in the current tick
 #[derive(Resource, Clone, Default)]
-pub struct Actions(Vec<Action>);
+pub struct Actions(pub Vec<PlayerAction>);
 
 type BoxedIt<T> = Box<dyn Iterator<Item = T> + Send>;
 type BoxedGenerator<T> = Box<dyn FnMut() -> T + Send>;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum SimulationPacket {
     ActionPacket(ActionPacket),
+    InputBatch(InputBatch),
+    /// Acknowledges every action of the sender up to this tick
+    InputAck(Tick),
     ResetRequest(ResetRequest),
+    Checksum(ChecksumPacket),
+    Desync(DesyncReport),
+    /// Asks a peer of a running match for the snapshot of a tick to bring a late joiner up to date
+    StateRequest(Tick),
+    StateSnapshot(TickSnapshot),
+    StateTransfer(StateTransfer),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -55,21 +86,11 @@ pub enum ResetRequest {
 }
 
 impl ResetRequest {
-    pub fn sleep_until(&self) {
+    /// Blocks until the requested time has been reached on the synchronized clock
+    pub fn sleep_until(&self, clock: &SyncedClock) {
         match self {
             ResetRequest::Now => (),
-            ResetRequest::AtTime(time_point) => {
-                let client = SntpClient::new();
-                let sleep_millis = time_point.millis()
-                    - client
-                        .synchronize("time.google.com")
-                        .unwrap()
-                        .datetime()
-                        .unix_timestamp()
-                        .unwrap()
-                        .as_millis();
-                sleep(Duration::from_millis(sleep_millis as u64))
-            }
+            ResetRequest::AtTime(time_point) => sleep(clock.duration_until(*time_point)),
         }
     }
 }
@@ -79,8 +100,32 @@ pub struct SimulationRunConfig {
     action_packets: BoxedIt<ActionPacket>,
     state_complete_handler: Vec<SimulationStateHandler>,
     local_action_packet_callbacks: Vec<LocalActionPacketHandler>,
+    apply_local_actions: bool,
+    local_player: PlayerId,
+    input_delay: InputDelay,
+    rtt_source: BoxedGenerator<Option<Duration>>,
+    input_buffer: InputBuffer,
+    input_acks: BoxedIt<Tick>,
+    input_batch_callbacks: Vec<InputBatchHandler>,
+    checksum_callbacks: Vec<ChecksumHandler>,
+    stats: SimulationStats,
+    stats_callbacks: Vec<StatsHandler>,
+    replay_recorder: Option<ReplayRecorder>,
+    replay_callbacks: Vec<ReplayHandler>,
+    snapshot_requests: BoxedIt<Tick>,
+    pending_snapshot_requests: Vec<Tick>,
+    snapshot_callbacks: Vec<SnapshotHandler>,
+    state_transfers: BoxedIt<StateTransfer>,
     should_reset_generator: BoxedGenerator<Option<ResetRequest>>,
-    action_cache: HashMap<Tick, Vec<Action>>,
+    should_stop_generator: BoxedGenerator<bool>,
+    synced_clock: SyncedClock,
+    action_cache: HashMap<Tick, Vec<PlayerAction>>,
+    next_local_sequence: u64,
+    rollback_buffer: RollbackBuffer,
+    clock_mode: ClockMode,
+    tick_rate: TickRate,
+    max_catch_up_ticks: u64,
+    last_tick: Option<Tick>,
 }
 
 impl Default for SimulationRunConfig {
@@ -90,14 +135,38 @@ impl Default for SimulationRunConfig {
             action_packets: Box::new(iter::from_fn(|| None)),
             state_complete_handler: Default::default(),
             local_action_packet_callbacks: Default::default(),
+            apply_local_actions: true,
+            local_player: Default::default(),
+            input_delay: Default::default(),
+            rtt_source: Box::new(|| None),
+            input_buffer: Default::default(),
+            input_acks: Box::new(iter::from_fn(|| None)),
+            input_batch_callbacks: Default::default(),
+            checksum_callbacks: Default::default(),
+            stats: Default::default(),
+            stats_callbacks: Default::default(),
+            replay_recorder: None,
+            replay_callbacks: Default::default(),
+            snapshot_requests: Box::new(iter::from_fn(|| None)),
+            pending_snapshot_requests: vec![],
+            snapshot_callbacks: Default::default(),
+            state_transfers: Box::new(iter::from_fn(|| None)),
             should_reset_generator: Box::new(|| None),
+            should_stop_generator: Box::new(|| false),
+            synced_clock: Default::default(),
             action_cache: Default::default(),
+            next_local_sequence: 0,
+            rollback_buffer: Default::default(),
+            clock_mode: Default::default(),
+            tick_rate: Default::default(),
+            max_catch_up_ticks: 5,
+            last_tick: None,
         }
     }
 }
 
 pub trait Simulation {
-    fn step_simulation(&mut self, dt: Duration, actions: Vec<Action>);
+    fn step_simulation(&mut self, dt: DeltaTime, actions: Vec<PlayerAction>);
 }
 
 #[derive(Default)]
@@ -114,36 +183,258 @@ impl SimulationImpl {
         }
     }
 
-    pub fn run(&mut self, mut run_options: SimulationRunConfig) -> ! {
-        let target_dt_ms = 16;
-        let mut start_time = Instant::now();
-        let mut root_time = start_time;
-        loop {
-            let frame_time = Instant::now() - start_time;
-            let actions = self.handle_actions(
-                &mut run_options.actions,
-                &mut run_options.action_packets,
-                &mut run_options.local_action_packet_callbacks,
-                &mut run_options.action_cache,
+    /// Runs until the stop signal of the `SimulationRunConfig` is raised or the last tick ran
+    pub fn run(&mut self, mut run_options: SimulationRunConfig) {
+        // Playback always advances by fixed ticks, measured ticks could not be reproduced
+        if run_options.clock_mode == ClockMode::WallClock
+            && run_options.replay_recorder.take().is_some()
+        {
+            warn!("Replays cannot be recorded with a wall clock, not recording this run");
+        }
+        let tick_duration = run_options.tick_rate.tick_duration();
+        let mut root_time = Instant::now();
+        let mut root_tick = self.simulation_state.current_tick();
+        let mut last_tick_time = root_time;
+        while !(run_options.should_stop_generator)() {
+            if self.ran_last_tick(&run_options) {
+                info!("Stopping after the last tick ran");
+                break;
+            }
+            if let Some(state_transfer) = run_options.state_transfers.next() {
+                self.apply_state_transfer(&mut run_options, state_transfer);
+                root_time = Instant::now();
+                root_tick = self.simulation_state.current_tick();
+                last_tick_time = root_time;
+            }
+
+            let elapsed_ticks =
+                ((Instant::now() - root_time).as_nanos() / tick_duration.as_nanos()) as u64 + 1;
+            let mut ticks_behind = (root_tick.0 + elapsed_ticks)
+                .saturating_sub(self.simulation_state.current_tick().0);
+            if ticks_behind > run_options.max_catch_up_ticks {
+                let skipped_ticks = ticks_behind - run_options.max_catch_up_ticks;
+                warn!(
+                    "Simulation is {} ticks behind, delaying {} ticks",
+                    ticks_behind, skipped_ticks
+                );
+                root_time += run_options.tick_rate.ticks_duration(skipped_ticks);
+                ticks_behind = run_options.max_catch_up_ticks;
+            }
+
+            for _ in 0..ticks_behind {
+                if self.ran_last_tick(&run_options) {
+                    break;
+                }
+                let dt = match run_options.clock_mode {
+                    ClockMode::Fixed => DeltaTime::from(run_options.tick_rate),
+                    ClockMode::WallClock => DeltaTime::from(Instant::now() - last_tick_time),
+                };
+                last_tick_time = Instant::now();
+
+                if let Some(reset_request) = self.tick(&mut run_options, dt) {
+                    reset_request.sleep_until(&run_options.synced_clock);
+                    root_time = Instant::now();
+                    root_tick = self.simulation_state.current_tick();
+                    last_tick_time = root_time;
+                    break;
+                }
+            }
+
+            let next_tick_time = root_time
+                + run_options
+                    .tick_rate
+                    .ticks_duration(self.simulation_state.current_tick().0 - root_tick.0);
+            sleep(next_tick_time.saturating_duration_since(Instant::now()));
+        }
+        Self::finish_replay(&mut run_options);
+    }
+
+    fn ran_last_tick(&self, run_options: &SimulationRunConfig) -> bool {
+        run_options
+            .last_tick
+            .is_some_and(|last_tick| self.simulation_state.current_tick() > last_tick)
+    }
+
+    /// Runs a single tick and returns the `ResetRequest` if the simulation was reset afterwards
+    fn tick(
+        &mut self,
+        run_options: &mut SimulationRunConfig,
+        dt: DeltaTime,
+    ) -> Option<ResetRequest> {
+        let rollback_tick = self.handle_actions(run_options);
+        Self::send_inputs(run_options);
+        if let Some(rollback_tick) = rollback_tick {
+            run_options.stats.rollbacks += 1;
+            run_options.stats.resimulated_ticks +=
+                self.simulation_state.current_tick().0 - rollback_tick.0;
+            self.rollback(
+                rollback_tick,
+                &mut run_options.rollback_buffer,
+                &run_options.action_cache,
             );
-            self.step_simulation(frame_time, actions);
+        }
 
-            if let Some(reset_request) = (run_options.should_reset_generator)() {
-                self.simulation_state.reset();
-                run_options.action_cache.clear();
-                reset_request.sleep_until();
-                root_time = Instant::now();
+        run_options
+            .pending_snapshot_requests
+            .extend(run_options.snapshot_requests.by_ref());
+        self.publish_snapshots(run_options);
+
+        let current_tick = self.simulation_state.current_tick();
+        let finalized_entry =
+            run_options
+                .rollback_buffer
+                .push(current_tick, dt, self.simulation_state.snapshot());
+        if let Some(finalized_entry) = finalized_entry {
+            Self::record_tick(run_options, &finalized_entry);
+            let checksum_packet = ChecksumPacket {
+                tick: finalized_entry.tick,
+                checksum: finalized_entry.snapshot.checksum(),
+            };
+            for handler in &mut run_options.checksum_callbacks {
+                handler(&checksum_packet);
             }
+        }
+        let actions = run_options
+            .action_cache
+            .get(&current_tick)
+            .cloned()
+            .unwrap_or_default();
+        self.step_simulation(dt, actions);
+
+        if let Some(oldest_tick) = run_options.rollback_buffer.oldest_tick() {
+            run_options
+                .action_cache
+                .retain(|tick, _| *tick >= oldest_tick);
+        }
+
+        let reset_request = (run_options.should_reset_generator)();
+        if reset_request.is_some() {
+            Self::finish_replay(run_options);
+            self.simulation_state.reset();
+            run_options.action_cache.clear();
+            run_options.rollback_buffer.clear();
+            run_options.input_buffer.clear();
+            run_options.pending_snapshot_requests.clear();
+        }
+
+        for handler in &mut run_options.state_complete_handler {
+            handler(&mut self.simulation_state)
+        }
+        self.simulation_state.set_history_rewritten_from(None);
+
+        run_options.stats.tick = self.simulation_state.current_tick();
+        for handler in &mut run_options.stats_callbacks {
+            handler(&run_options.stats);
+        }
+
+        reset_request
+    }
 
-            for handler in &mut run_options.state_complete_handler {
-                handler(&mut self.simulation_state)
+    /// Publishes the snapshots of requested ticks once they are the oldest of the rollback window,
+    /// later ticks may still be rewritten
+    fn publish_snapshots(&self, run_options: &mut SimulationRunConfig) {
+        let current_tick = self.simulation_state.current_tick();
+        let oldest_tick = run_options.rollback_buffer.oldest_tick();
+        let mut tick_snapshots = vec![];
+        run_options.pending_snapshot_requests.retain(|tick| {
+            match run_options.rollback_buffer.oldest() {
+                Some(entry) if entry.tick == *tick => tick_snapshots.push(TickSnapshot {
+                    tick: entry.tick,
+                    snapshot: entry.snapshot.clone(),
+                }),
+                None if current_tick == *tick => tick_snapshots.push(TickSnapshot {
+                    tick: current_tick,
+                    snapshot: self.simulation_state.snapshot(),
+                }),
+                _ if *tick >= oldest_tick.unwrap_or(current_tick) => return true,
+                _ => warn!(
+                    "Cannot provide the snapshot of tick {}, it is no longer simulated",
+                    tick.0
+                ),
             }
+            false
+        });
+        for tick_snapshot in &tick_snapshots {
+            for handler in &mut run_options.snapshot_callbacks {
+                handler(tick_snapshot);
+            }
+        }
+    }
 
-            start_time = Instant::now();
-            let sleep_target = root_time
-                + Duration::from_millis(target_dt_ms)
-                    * self.simulation_state.current_tick().0 as u32;
-            sleep(sleep_target - Instant::now());
+    /// Restores the transferred snapshot and re-simulates the transferred actions up to the
+    /// tick that the other peers of the match are at
+    fn apply_state_transfer(
+        &mut self,
+        run_options: &mut SimulationRunConfig,
+        state_transfer: StateTransfer,
+    ) {
+        let live_tick = state_transfer.live_tick(&run_options.synced_clock, run_options.tick_rate);
+        let StateTransfer {
+            snapshot, actions, ..
+        } = state_transfer;
+        if let Err(e) = self.simulation_state.restore(&snapshot.snapshot) {
+            error!("Could not restore transferred state: {}", e);
+            return;
+        }
+        Self::finish_replay(run_options);
+        run_options.action_cache.clear();
+        run_options.rollback_buffer.clear();
+        run_options.input_buffer.clear();
+        for action_packet in actions {
+            cache_action(&mut run_options.action_cache, &action_packet);
+        }
+        debug!(
+            "Fast-forwarding transferred state from tick {} to tick {}",
+            snapshot.tick.0, live_tick.0
+        );
+
+        let dt = DeltaTime::from(run_options.tick_rate);
+        while self.simulation_state.current_tick() < live_tick {
+            let current_tick = self.simulation_state.current_tick();
+            run_options
+                .rollback_buffer
+                .push(current_tick, dt, self.simulation_state.snapshot());
+            let actions = run_options
+                .action_cache
+                .get(&current_tick)
+                .cloned()
+                .unwrap_or_default();
+            self.step_simulation(dt, actions);
+        }
+        self.simulation_state
+            .set_history_rewritten_from(Some(snapshot.tick));
+    }
+
+    fn record_tick(run_options: &mut SimulationRunConfig, entry: &HistoryEntry) {
+        let Some(replay_recorder) = &mut run_options.replay_recorder else {
+            return;
+        };
+        let actions = run_options
+            .action_cache
+            .get(&entry.tick)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        replay_recorder.record(entry, actions, run_options.tick_rate);
+    }
+
+    /// Records the ticks that are still in the rollback window and hands out the replay
+    fn finish_replay(run_options: &mut SimulationRunConfig) {
+        let Some(replay_recorder) = &mut run_options.replay_recorder else {
+            return;
+        };
+        for entry in run_options.rollback_buffer.entries() {
+            let actions = run_options
+                .action_cache
+                .get(&entry.tick)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            replay_recorder.record(entry, actions, run_options.tick_rate);
+        }
+        let Some(replay) = replay_recorder.finish() else {
+            return;
+        };
+        for handler in &mut run_options.replay_callbacks {
+            handler(&replay);
         }
     }
 
@@ -151,45 +442,126 @@ impl SimulationImpl {
         &self.simulation_state
     }
 
-    fn handle_actions(
-        &mut self,
-        actions: &mut BoxedIt<Action>,
-        action_packets: &mut BoxedIt<ActionPacket>,
-        local_action_packet_handlers: &mut [LocalActionPacketHandler],
-        action_cache: &mut HashMap<Tick, Vec<Action>>,
-    ) -> Vec<Action> {
-        for local_action_packet in actions
-            .map(|action| ActionPacket::new(self.simulation_state.current_tick() + Tick(0), action))
-        {
-            for handler in local_action_packet_handlers.iter_mut() {
+    /// Caches all incoming actions and returns the earliest past tick that has to be re-simulated
+    fn handle_actions(&mut self, run_options: &mut SimulationRunConfig) -> Option<Tick> {
+        let SimulationRunConfig {
+            actions,
+            action_packets,
+            local_action_packet_callbacks,
+            apply_local_actions,
+            local_player,
+            input_delay,
+            rtt_source,
+            input_buffer,
+            input_batch_callbacks,
+            action_cache,
+            next_local_sequence,
+            rollback_buffer,
+            tick_rate,
+            stats,
+            ..
+        } = run_options;
+        let current_tick = self.simulation_state.current_tick();
+        let input_delay_ticks = input_delay.ticks(rtt_source(), *tick_rate);
+        stats.input_delay = input_delay_ticks;
+        let local_action_tick = input_buffer.next_tick(current_tick, input_delay_ticks);
+        for action in actions {
+            let local_action_packet = ActionPacket::new(
+                local_action_tick,
+                *local_player,
+                *next_local_sequence,
+                action,
+            );
+            *next_local_sequence += 1;
+            for handler in local_action_packet_callbacks.iter_mut() {
                 handler(&local_action_packet);
             }
-
-            let actions_for_tick = action_cache.entry(local_action_packet.tick).or_default();
-            actions_for_tick.push(local_action_packet.action);
+            if *apply_local_actions {
+                cache_action(action_cache, &local_action_packet);
+            }
+            // Nothing acknowledges the actions if they are not sent
+            if !input_batch_callbacks.is_empty() {
+                input_buffer.push(local_action_packet);
+            }
         }
+
+        let mut rollback_tick: Option<Tick> = None;
         for action_packet in action_packets {
-            if action_packet.tick < self.simulation_state.current_tick() {
-                error!(
-                    "ActionPacket lies in the past!\nCurrent Tick: {}\n{:?}",
-                    self.simulation_state.current_tick().0,
-                    action_packet
-                );
+            if action_packet.tick < current_tick {
+                if !rollback_buffer.contains(action_packet.tick) {
+                    error!(
+                        "ActionPacket lies too far in the past to roll back!\nCurrent Tick: {}\n{:?}",
+                        current_tick.0, action_packet
+                    );
+                    continue;
+                }
+                rollback_tick = Some(match rollback_tick {
+                    Some(tick) => tick.min(action_packet.tick),
+                    None => action_packet.tick,
+                });
             }
-            let actions_for_tick = action_cache.entry(action_packet.tick).or_default();
-            actions_for_tick.push(action_packet.action);
+            cache_action(action_cache, &action_packet);
+        }
+        rollback_tick
+    }
+
+    /// Hands the unacknowledged local actions to the input batch callbacks
+    fn send_inputs(run_options: &mut SimulationRunConfig) {
+        for ack in run_options.input_acks.by_ref() {
+            run_options.input_buffer.acknowledge(ack);
+        }
+        if run_options.input_batch_callbacks.is_empty() {
+            return;
         }
-        let actions_in_table = action_cache
-            .entry(self.simulation_state.current_tick())
-            .or_default();
-        std::mem::take(actions_in_table)
+        let Some(input_batch) = run_options.input_buffer.batch() else {
+            return;
+        };
+        for handler in &mut run_options.input_batch_callbacks {
+            handler(&input_batch);
+        }
+    }
+
+    fn rollback(
+        &mut self,
+        tick: Tick,
+        rollback_buffer: &mut RollbackBuffer,
+        action_cache: &HashMap<Tick, Vec<PlayerAction>>,
+    ) {
+        let current_tick = self.simulation_state.current_tick();
+        let history = rollback_buffer.drain_from(tick);
+        let Some(first_entry) = history.first() else {
+            return;
+        };
+        debug!(
+            "Rolling back from tick {} to tick {}",
+            current_tick.0, tick.0
+        );
+
+        self.simulation_state
+            .restore(&first_entry.snapshot)
+            .expect("snapshots taken by this simulation to be restorable");
+        for entry in history {
+            rollback_buffer.push(entry.tick, entry.dt, self.simulation_state.snapshot());
+            let actions = action_cache.get(&entry.tick).cloned().unwrap_or_default();
+            self.step_simulation(entry.dt, actions);
+        }
+        self.simulation_state.set_history_rewritten_from(Some(tick));
     }
 }
 
+/// Keeps the actions of a tick sorted, packets of different players arrive in any order
+fn cache_action(action_cache: &mut HashMap<Tick, Vec<PlayerAction>>, action_packet: &ActionPacket) {
+    let player_action = action_packet.player_action();
+    let actions_for_tick = action_cache.entry(action_packet.tick).or_default();
+    let index =
+        actions_for_tick.partition_point(|cached| cached.order_key() <= player_action.order_key());
+    actions_for_tick.insert(index, player_action);
+}
+
 impl Simulation for SimulationImpl {
-    fn step_simulation(&mut self, dt: Duration, actions: Vec<Action>) {
+    fn step_simulation(&mut self, dt: DeltaTime, actions: Vec<PlayerAction>) {
         self.simulation_state.load_actions(Actions(actions));
-        self.simulation_state.load_delta_time(dt.into());
+        self.simulation_state.load_delta_time(dt);
         self.schedule.run(self.simulation_state.world_mut());
         self.simulation_state.advance_tick();
     }
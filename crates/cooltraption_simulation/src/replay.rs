@@ -0,0 +1,238 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::action::{ActionPacket, PlayerAction};
+use crate::builders::{SimulationImplBuilder, SimulationRunOptionsBuilder};
+use crate::clock::{ClockMode, TickRate};
+use crate::rollback::HistoryEntry;
+use crate::state_transfer::TickSnapshot;
+use crate::Tick;
+
+/// Bumped whenever the replay format changes
+const REPLAY_VERSION: u32 = 1;
+
+/// Everything that is needed to re-simulate a match tick by tick.
+/// Playing it back only reproduces the match with the schedule it was recorded with.
+/// Only runs with `ClockMode::Fixed` are recorded, so every tick advances by the tick rate.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Replay {
+    version: u32,
+    /// Names the schedule the replay was recorded with
+    pub schedule: String,
+    pub tick_rate: TickRate,
+    pub initial_state: TickSnapshot,
+    /// Last tick that was recorded, playback has to run up to the end of this tick
+    pub last_tick: Tick,
+    /// Every action that was executed, ordered by tick
+    pub action_packets: Vec<ActionPacket>,
+}
+
+impl Replay {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create replay file {}", path.display()))?;
+        bincode::serialize_into(BufWriter::new(file), self)
+            .with_context(|| format!("Could not write replay file {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Could not open replay file {}", path.display()))?;
+        let replay: Replay = bincode::deserialize_from(BufReader::new(file))
+            .with_context(|| format!("Could not read replay file {}", path.display()))?;
+        if replay.version != REPLAY_VERSION {
+            bail!(
+                "Replay file {} has version {}, expected {}",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            );
+        }
+        Ok(replay)
+    }
+}
+
+/// Collects the actions of every tick once it can no longer be rolled back
+pub struct ReplayRecorder {
+    schedule: String,
+    replay: Option<Replay>,
+}
+
+impl ReplayRecorder {
+    /// `schedule` has to name the schedule of the simulation, playback refuses other schedules
+    pub fn new(schedule: impl Into<String>) -> Self {
+        Self {
+            schedule: schedule.into(),
+            replay: None,
+        }
+    }
+
+    /// Records an executed tick, the first recorded tick provides the initial state
+    pub fn record(&mut self, entry: &HistoryEntry, actions: &[PlayerAction], tick_rate: TickRate) {
+        let replay = self.replay.get_or_insert_with(|| Replay {
+            version: REPLAY_VERSION,
+            schedule: self.schedule.clone(),
+            tick_rate,
+            initial_state: TickSnapshot {
+                tick: entry.tick,
+                snapshot: entry.snapshot.clone(),
+            },
+            last_tick: entry.tick,
+            action_packets: vec![],
+        });
+        replay.last_tick = entry.tick;
+        replay.action_packets.extend(actions.iter().map(|action| {
+            ActionPacket::new(
+                entry.tick,
+                action.player,
+                action.sequence,
+                action.action.clone(),
+            )
+        }));
+    }
+
+    /// Returns the recorded replay and starts a new one with the next recorded tick
+    pub fn finish(&mut self) -> Option<Replay> {
+        self.replay.take()
+    }
+}
+
+/// Feeds a recorded replay back into a simulation
+pub struct ReplayPlayback {
+    replay: Replay,
+}
+
+impl ReplayPlayback {
+    /// Fails if the replay was recorded with a different schedule
+    pub fn new(replay: Replay, schedule: &str) -> Result<Self> {
+        if replay.schedule != schedule {
+            bail!(
+                "Replay was recorded with schedule {}, not {}",
+                replay.schedule,
+                schedule
+            );
+        }
+        Ok(Self { replay })
+    }
+
+    pub fn restore_initial_state(
+        &self,
+        simulation_builder: &mut SimulationImplBuilder,
+    ) -> Result<()> {
+        simulation_builder
+            .restore_state(&self.replay.initial_state.snapshot)
+            .context("Could not restore the initial state of the replay")?;
+        Ok(())
+    }
+
+    /// Schedules every recorded action at its tick and stops the simulation after the last tick.
+    /// Local actions are no longer applied, so they cannot alter the replayed match.
+    pub fn configure_run_options(self, run_options_builder: &mut SimulationRunOptionsBuilder) {
+        // Actions are cached until their tick is reached, so they can all be handed over at once
+        run_options_builder
+            .set_tick_rate(self.replay.tick_rate)
+            .set_clock_mode(ClockMode::Fixed)
+            .set_last_tick(self.replay.last_tick)
+            .set_apply_local_actions(false)
+            .set_action_packets(Box::new(self.replay.action_packets.into_iter()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::snapshot::SimulationStateSnapshot;
+    use crate::system_sets::physics_set::DeltaTime;
+    use cooltraption_common::types::PlayerId;
+
+    fn history_entry(tick: u64) -> HistoryEntry {
+        HistoryEntry {
+            tick: Tick(tick),
+            dt: DeltaTime::default(),
+            snapshot: SimulationStateSnapshot::from_bytes(vec![tick as u8]),
+        }
+    }
+
+    fn player_action(player: u64, sequence: u64) -> PlayerAction {
+        PlayerAction {
+            player: PlayerId(player),
+            sequence,
+            action: Action {
+                name: String::from("noop"),
+                version: 0,
+                payload: vec![sequence as u8],
+            },
+        }
+    }
+
+    fn recorded_actions(replay: &Replay) -> Vec<(Tick, PlayerId, u64)> {
+        replay
+            .action_packets
+            .iter()
+            .map(|action_packet| {
+                (
+                    action_packet.tick,
+                    action_packet.player,
+                    action_packet.sequence,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recorded_replays_survive_a_save_and_load() {
+        let tick_rate = TickRate::new(30).unwrap();
+        let mut replay_recorder = ReplayRecorder::new("test");
+        replay_recorder.record(&history_entry(4), &[player_action(0, 0)], tick_rate);
+        replay_recorder.record(&history_entry(5), &[], tick_rate);
+        replay_recorder.record(
+            &history_entry(6),
+            &[player_action(0, 1), player_action(1, 0)],
+            tick_rate,
+        );
+        let replay = replay_recorder.finish().unwrap();
+        assert!(replay_recorder.finish().is_none());
+
+        assert_eq!(replay.initial_state.tick, Tick(4));
+        assert_eq!(replay.initial_state.snapshot.as_bytes(), &[4]);
+        assert_eq!(replay.last_tick, Tick(6));
+        let expected_actions = vec![
+            (Tick(4), PlayerId(0), 0),
+            (Tick(6), PlayerId(0), 1),
+            (Tick(6), PlayerId(1), 0),
+        ];
+        assert_eq!(recorded_actions(&replay), expected_actions);
+
+        let path = std::env::temp_dir().join(format!("replay_test_{}.bin", std::process::id()));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.schedule, "test");
+        assert_eq!(loaded.tick_rate, tick_rate);
+        assert_eq!(loaded.initial_state.tick, Tick(4));
+        assert_eq!(loaded.initial_state.snapshot, replay.initial_state.snapshot);
+        assert_eq!(loaded.last_tick, Tick(6));
+        assert_eq!(recorded_actions(&loaded), expected_actions);
+        assert_eq!(
+            loaded.action_packets[2].action,
+            replay.action_packets[2].action
+        );
+    }
+
+    #[test]
+    fn playback_refuses_replays_of_other_schedules() {
+        let mut replay_recorder = ReplayRecorder::new("test");
+        replay_recorder.record(&history_entry(0), &[], TickRate::new(30).unwrap());
+        let replay = replay_recorder.finish().unwrap();
+
+        assert!(ReplayPlayback::new(replay.clone(), "other").is_err());
+        assert!(ReplayPlayback::new(replay, "test").is_ok());
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+
+use crate::snapshot::SimulationStateSnapshot;
+use crate::system_sets::physics_set::DeltaTime;
+use crate::Tick;
+
+pub struct HistoryEntry {
+    pub tick: Tick,
+    pub dt: DeltaTime,
+    pub snapshot: SimulationStateSnapshot,
+}
+
+pub struct RollbackBuffer {
+    depth: usize,
+    history: VecDeque<HistoryEntry>,
+}
+
+impl Default for RollbackBuffer {
+    fn default() -> Self {
+        Self::new(60)
+    }
+}
+
+impl RollbackBuffer {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            history: VecDeque::with_capacity(depth),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the entry that dropped out of the buffer, its tick can no longer be rolled back
+    pub fn push(
+        &mut self,
+        tick: Tick,
+        dt: DeltaTime,
+        snapshot: SimulationStateSnapshot,
+    ) -> Option<HistoryEntry> {
+        let entry = HistoryEntry { tick, dt, snapshot };
+        if self.depth == 0 {
+            return Some(entry);
+        }
+        let evicted = if self.history.len() >= self.depth {
+            self.history.pop_front()
+        } else {
+            None
+        };
+        self.history.push_back(entry);
+        evicted
+    }
+
+    pub fn oldest_tick(&self) -> Option<Tick> {
+        self.history.front().map(|entry| entry.tick)
+    }
+
+    /// Entry that is the least likely to be rolled back again
+    pub fn oldest(&self) -> Option<&HistoryEntry> {
+        self.history.front()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history.iter()
+    }
+
+    pub fn contains(&self, tick: Tick) -> bool {
+        self.index_of(tick).is_some()
+    }
+
+    /// Removes and returns every entry starting at `tick`, oldest first
+    pub fn drain_from(&mut self, tick: Tick) -> Vec<HistoryEntry> {
+        match self.index_of(tick) {
+            Some(index) => self.history.drain(index..).collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    fn index_of(&self, tick: Tick) -> Option<usize> {
+        let oldest = self.oldest_tick()?;
+        if tick < oldest {
+            return None;
+        }
+        let index = (tick.0 - oldest.0) as usize;
+        (index < self.history.len()).then_some(index)
+    }
+}
@@ -1,18 +1,31 @@
 use bevy_ecs::prelude::{Component, World};
 use bevy_ecs::query::{QueryIter, WorldQuery};
 
+use crate::components::NetIds;
+use crate::snapshot::{SimulationStateSnapshot, SnapshotRegistry, StateChecksum};
+use crate::system_sets::collision_set::Collisions;
+use crate::system_sets::lifecycle_set::{EntityCap, WorldBounds};
+use crate::system_sets::spatial_set::SpatialHash;
 use crate::{system_sets::physics_set::DeltaTime, Actions, Tick};
 
 pub struct SimulationState {
     world: World,
+    snapshot_registry: SnapshotRegistry,
+    history_rewritten_from: Option<Tick>,
+    world_bounds: Option<WorldBounds>,
+    entity_cap: Option<EntityCap>,
 }
 
 impl Default for SimulationState {
     fn default() -> Self {
         let mut state = Self {
             world: Default::default(),
+            snapshot_registry: Default::default(),
+            history_rewritten_from: None,
+            world_bounds: None,
+            entity_cap: None,
         };
-        state.load_current_tick(Tick(0));
+        state.reset();
         state
     }
 }
@@ -63,5 +76,51 @@ impl SimulationState {
     pub fn reset(&mut self) {
         self.world.clear_all();
         self.load_current_tick(Tick(0));
+        self.world.init_resource::<Collisions>();
+        self.world.init_resource::<SpatialHash>();
+        self.world.init_resource::<NetIds>();
+        if let Some(world_bounds) = self.world_bounds {
+            self.world.insert_resource(world_bounds);
+        }
+        if let Some(entity_cap) = self.entity_cap {
+            self.world.insert_resource(entity_cap);
+        }
+    }
+
+    /// Kept across resets
+    pub fn set_world_bounds(&mut self, world_bounds: WorldBounds) {
+        self.world_bounds = Some(world_bounds);
+        self.world.insert_resource(world_bounds);
+    }
+
+    /// Kept across resets
+    pub fn set_entity_cap(&mut self, entity_cap: EntityCap) {
+        self.entity_cap = Some(entity_cap);
+        self.world.insert_resource(entity_cap);
+    }
+
+    pub fn snapshot(&self) -> SimulationStateSnapshot {
+        self.snapshot_registry.snapshot(&self.world)
+    }
+
+    pub fn checksum(&self) -> StateChecksum {
+        self.snapshot().checksum()
+    }
+
+    pub fn restore(&mut self, snapshot: &SimulationStateSnapshot) -> anyhow::Result<()> {
+        self.snapshot_registry.restore(&mut self.world, snapshot)
+    }
+
+    pub fn snapshot_registry_mut(&mut self) -> &mut SnapshotRegistry {
+        &mut self.snapshot_registry
+    }
+
+    /// Set when the last completed tick was reached by re-simulating from an earlier tick
+    pub fn history_rewritten_from(&self) -> Option<Tick> {
+        self.history_rewritten_from
+    }
+
+    pub fn set_history_rewritten_from(&mut self, tick: Option<Tick>) {
+        self.history_rewritten_from = tick;
     }
 }
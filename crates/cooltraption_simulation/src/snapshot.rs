@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use bevy_ecs::prelude::{Component, World};
+use bevy_ecs::world::{EntityMut, EntityRef};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::components::{
+    Acceleration, Drag, Drawable, Force, Lifetime, NetId, NetIds, Owner, Position, SpawnedAt,
+    Velocity, Weight,
+};
+use crate::system_sets::collision_set::Collider;
+use crate::system_sets::physics_set::DeltaTime;
+use crate::Tick;
+
+type SerializeFn = fn(&EntityRef) -> Option<Vec<u8>>;
+type DeserializeFn = fn(&[u8]) -> Result<DecodedComponent>;
+/// Inserts a deserialized component into an entity
+type DecodedComponent = Box<dyn FnOnce(&mut EntityMut)>;
+
+/// Serialized world of a `SimulationState`.
+/// Two snapshots of identical worlds are guaranteed to contain the same bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SimulationStateSnapshot(Vec<u8>);
+
+impl SimulationStateSnapshot {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// FNV-1a hash of the snapshot bytes
+    pub fn checksum(&self) -> StateChecksum {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+        StateChecksum(self.0.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+        }))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StateChecksum(pub u64);
+
+#[derive(Serialize, Deserialize)]
+struct WorldData {
+    tick: Tick,
+    delta_time: Option<DeltaTime>,
+    net_ids: NetIds,
+    entities: Vec<EntityData>,
+}
+
+/// `Entity` ids are left out, they differ between peers that rolled back and peers that did not
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct EntityData {
+    components: Vec<(u16, Vec<u8>)>,
+}
+
+#[derive(Clone, Copy)]
+struct ComponentSerializer {
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+/// Components are identified by their registration index, so every peer has to register
+/// the same components in the same order.
+#[derive(Clone)]
+pub struct SnapshotRegistry {
+    components: Vec<ComponentSerializer>,
+}
+
+impl Default for SnapshotRegistry {
+    fn default() -> Self {
+        let mut registry = Self { components: vec![] };
+        registry
+            .register::<Position>()
+            .register::<Velocity>()
+            .register::<Acceleration>()
+            .register::<Weight>()
+            .register::<Force>()
+            .register::<Drawable>()
+            .register::<Owner>()
+            .register::<Collider>()
+            .register::<Drag>()
+            .register::<Lifetime>()
+            .register::<SpawnedAt>()
+            .register::<NetId>();
+        registry
+    }
+}
+
+impl SnapshotRegistry {
+    pub fn register<C: Component + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+        self.components.push(ComponentSerializer {
+            serialize: serialize_component::<C>,
+            deserialize: deserialize_component::<C>,
+        });
+        self
+    }
+
+    pub fn snapshot(&self, world: &World) -> SimulationStateSnapshot {
+        let mut entities: Vec<EntityData> = world
+            .iter_entities()
+            .map(|entity_ref| EntityData {
+                components: self
+                    .components
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, component)| {
+                        (component.serialize)(&entity_ref).map(|bytes| (index as u16, bytes))
+                    })
+                    .collect(),
+            })
+            .collect();
+        // Sorting by content keeps the order independent of the `Entity` ids
+        entities.sort();
+
+        let world_data = WorldData {
+            tick: *world.resource::<Tick>(),
+            delta_time: world.get_resource::<DeltaTime>().copied(),
+            net_ids: world.get_resource::<NetIds>().copied().unwrap_or_default(),
+            entities,
+        };
+        SimulationStateSnapshot(
+            encoding()
+                .serialize(&world_data)
+                .expect("world data to be serializable"),
+        )
+    }
+
+    pub fn restore(&self, world: &mut World, snapshot: &SimulationStateSnapshot) -> Result<()> {
+        let world_data: WorldData = encoding().deserialize(snapshot.as_bytes())?;
+        // Everything is decoded before the world is cleared, a corrupt snapshot leaves it untouched
+        let entities = world_data
+            .entities
+            .iter()
+            .map(|entity_data| {
+                entity_data
+                    .components
+                    .iter()
+                    .map(|(index, bytes)| {
+                        let component = self
+                            .components
+                            .get(*index as usize)
+                            .ok_or_else(|| anyhow!("Component {} is not registered", index))?;
+                        (component.deserialize)(bytes)
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        world.clear_entities();
+        for components in entities {
+            let mut entity_mut = world.spawn_empty();
+            for insert in components {
+                insert(&mut entity_mut);
+            }
+        }
+
+        world.insert_resource(world_data.tick);
+        world.insert_resource(world_data.net_ids);
+        match world_data.delta_time {
+            Some(delta_time) => world.insert_resource(delta_time),
+            None => {
+                world.remove_resource::<DeltaTime>();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encoding() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+fn serialize_component<C: Component + Serialize>(entity_ref: &EntityRef) -> Option<Vec<u8>> {
+    entity_ref.get::<C>().map(|component| {
+        encoding()
+            .serialize(component)
+            .expect("component to be serializable")
+    })
+}
+
+fn deserialize_component<C: Component + DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<DecodedComponent> {
+    let component: C = encoding().deserialize(bytes)?;
+    Ok(Box::new(move |entity_mut: &mut EntityMut| {
+        entity_mut.insert(component);
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_sets::physics_set::{FromNum2, Vec2f};
+
+    #[test]
+    fn failed_restore_leaves_the_world_untouched() {
+        let mut world = World::new();
+        world.insert_resource(Tick(7));
+        world.spawn((
+            Position(Vec2f::from_num(1, 2)),
+            Velocity(Vec2f::from_num(3, 4)),
+        ));
+        let snapshot = SnapshotRegistry::default().snapshot(&world);
+
+        let mut restored = World::new();
+        restored.insert_resource(Tick(3));
+        restored.spawn(Position(Vec2f::from_num(5, 6)));
+        // Velocity is not registered, so the second component of the snapshot cannot be decoded
+        let mut registry = SnapshotRegistry { components: vec![] };
+        registry.register::<Position>();
+
+        assert!(registry.restore(&mut restored, &snapshot).is_err());
+        assert_eq!(*restored.resource::<Tick>(), Tick(3));
+        assert_eq!(restored.entities().len(), 1);
+        let position = restored.query::<&Position>().single(&restored);
+        assert_eq!(position.0, Vec2f::from_num(5, 6));
+    }
+}
@@ -0,0 +1,36 @@
+use cooltraption_common::types::{SyncedClock, TimePoint};
+use serde::{Deserialize, Serialize};
+
+use crate::action::ActionPacket;
+use crate::clock::TickRate;
+use crate::snapshot::SimulationStateSnapshot;
+use crate::Tick;
+
+/// World at the start of `tick`, before the actions of `tick` were executed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TickSnapshot {
+    pub tick: Tick,
+    pub snapshot: SimulationStateSnapshot,
+}
+
+/// Everything a late joiner needs to catch up with a running match
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StateTransfer {
+    pub snapshot: TickSnapshot,
+    /// Every action that is executed at or after the tick of the snapshot
+    pub actions: Vec<ActionPacket>,
+    /// Start of tick 0, the same time point the other peers reset their simulation at
+    pub match_started_at: TimePoint,
+}
+
+impl StateTransfer {
+    /// Tick the other peers of the match are simulating right now
+    pub fn live_tick(&self, clock: &SyncedClock, tick_rate: TickRate) -> Tick {
+        let elapsed_millis = clock
+            .now()
+            .millis()
+            .saturating_sub(self.match_started_at.millis());
+        let tick_nanos = tick_rate.tick_duration().as_nanos();
+        Tick((elapsed_millis * 1_000_000 / tick_nanos) as u64)
+    }
+}
@@ -0,0 +1,12 @@
+use crate::Tick;
+
+/// Counters of a running simulation, published after every tick
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimulationStats {
+    pub tick: Tick,
+    pub rollbacks: u64,
+    /// Ticks that were simulated again because of rollbacks
+    pub resimulated_ticks: u64,
+    /// Ticks local actions are currently scheduled ahead
+    pub input_delay: u64,
+}
@@ -0,0 +1,327 @@
+use bevy_ecs::prelude::*;
+use nalgebra::ComplexField;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{entity_order, NetId, Position, Velocity, Weight};
+use crate::system_sets::physics_set::{Float, FromNum2, Vec2f};
+
+/// Share of the penetration that is corrected per tick, correcting all of it makes stacks jitter
+const POSITION_CORRECTION: f64 = 0.8;
+/// Penetration that is tolerated without positional correction
+const PENETRATION_SLOP: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ColliderShape {
+    Circle {
+        radius: Float,
+    },
+    /// Axis aligned box centered on the position of the entity
+    Aabb {
+        half_extents: Vec2f,
+    },
+}
+
+/// Entities with a collider but without a `Velocity` are static and never pushed away
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    /// Share of the approaching speed that is kept after a collision, 0 is perfectly inelastic
+    pub restitution: Float,
+    pub friction: Float,
+}
+
+impl Collider {
+    pub fn circle(radius: Float) -> Self {
+        Self::new(ColliderShape::Circle { radius })
+    }
+
+    pub fn aabb(half_extents: Vec2f) -> Self {
+        Self::new(ColliderShape::Aabb { half_extents })
+    }
+
+    fn new(shape: ColliderShape) -> Self {
+        Self {
+            shape,
+            restitution: Float::from_num(0.5),
+            friction: Float::from_num(0.2),
+        }
+    }
+
+    fn half_extents(&self) -> Vec2f {
+        match self.shape {
+            ColliderShape::Circle { radius } => Vec2f::new(radius, radius),
+            ColliderShape::Aabb { half_extents } => half_extents,
+        }
+    }
+}
+
+/// Two overlapping colliders, `normal` points from `a` to `b`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    pub normal: Vec2f,
+    pub penetration: Float,
+}
+
+/// Collisions of the current tick, ordered deterministically.
+/// Systems that run after `PhysicsSet::CollisionDetection` can react to them.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Collisions(pub Vec<CollisionEvent>);
+
+struct Body {
+    entity: Entity,
+    order: (u64, u64),
+    position: Vec2f,
+    collider: Collider,
+    min: Vec2f,
+    max: Vec2f,
+}
+
+/// Finds every overlapping pair of colliders with a sweep and prune along the x axis
+pub fn detect_collisions(
+    query: Query<(Entity, &Position, &Collider, Option<&NetId>)>,
+    mut collisions: ResMut<Collisions>,
+) {
+    let mut bodies: Vec<Body> = query
+        .iter()
+        .map(|(entity, position, collider, net_id)| {
+            let half_extents = collider.half_extents();
+            Body {
+                entity,
+                order: entity_order(entity, net_id),
+                position: position.0,
+                collider: *collider,
+                min: position.0 - half_extents,
+                max: position.0 + half_extents,
+            }
+        })
+        .collect();
+    // The query order depends on the archetype layout, which is not the same on every peer
+    bodies.sort_by(|a, b| a.min.x.0.cmp(&b.min.x.0).then(a.order.cmp(&b.order)));
+
+    collisions.0.clear();
+    for (index, a) in bodies.iter().enumerate() {
+        for b in bodies[index + 1..]
+            .iter()
+            .take_while(|b| b.min.x <= a.max.x)
+        {
+            if b.min.y > a.max.y || b.max.y < a.min.y {
+                continue;
+            }
+            if let Some((normal, penetration)) = contact(a, b) {
+                collisions.0.push(CollisionEvent {
+                    a: a.entity,
+                    b: b.entity,
+                    normal,
+                    penetration,
+                });
+            }
+        }
+    }
+}
+
+/// Pushes colliding entities apart and applies impulses with restitution and friction
+pub fn resolve_collisions(
+    mut query: Query<(
+        &mut Position,
+        Option<&mut Velocity>,
+        Option<&Weight>,
+        &Collider,
+    )>,
+    collisions: Res<Collisions>,
+) {
+    let zero = Float::from_num(0);
+    let one = Float::from_num(1);
+    for collision in &collisions.0 {
+        let Ok([a, b]) = query.get_many_mut([collision.a, collision.b]) else {
+            continue;
+        };
+        let (mut position_a, mut velocity_a, weight_a, collider_a) = a;
+        let (mut position_b, mut velocity_b, weight_b, collider_b) = b;
+        let inverse_mass =
+            |velocity: &Option<Mut<Velocity>>, weight: Option<&Weight>| match velocity {
+                Some(_) => weight.copied().unwrap_or_default().inverse(),
+                None => zero,
+            };
+        let inverse_mass_a = inverse_mass(&velocity_a, weight_a);
+        let inverse_mass_b = inverse_mass(&velocity_b, weight_b);
+        let inverse_mass_sum = inverse_mass_a + inverse_mass_b;
+        if inverse_mass_sum == zero {
+            continue;
+        }
+        let normal = collision.normal;
+
+        let slop = Float::from_num(PENETRATION_SLOP);
+        if collision.penetration > slop {
+            let correction = normal
+                * ((collision.penetration - slop) / inverse_mass_sum
+                    * Float::from_num(POSITION_CORRECTION));
+            position_a.0 -= correction * inverse_mass_a;
+            position_b.0 += correction * inverse_mass_b;
+        }
+
+        let velocity_of = |velocity: &Option<Mut<Velocity>>| {
+            velocity
+                .as_ref()
+                .map_or(Vec2f::from_num(0, 0), |velocity| velocity.0)
+        };
+        let relative_velocity = velocity_of(&velocity_b) - velocity_of(&velocity_a);
+        let normal_speed = relative_velocity.dot(&normal);
+        if normal_speed > zero {
+            // Already separating
+            continue;
+        }
+
+        let restitution = (collider_a.restitution + collider_b.restitution) / Float::from_num(2);
+        let normal_impulse = -(one + restitution) * normal_speed / inverse_mass_sum;
+        let mut impulse = normal * normal_impulse;
+
+        let tangent_velocity = relative_velocity - normal * normal_speed;
+        let tangent_speed = tangent_velocity.norm();
+        if tangent_speed > zero {
+            let tangent = tangent_velocity / tangent_speed;
+            let friction = (collider_a.friction + collider_b.friction) / Float::from_num(2);
+            // Coulomb friction never exceeds the normal impulse scaled by the friction
+            let max_friction_impulse = friction * normal_impulse;
+            let mut friction_impulse = tangent_speed / inverse_mass_sum;
+            if friction_impulse > max_friction_impulse {
+                friction_impulse = max_friction_impulse;
+            }
+            impulse -= tangent * friction_impulse;
+        }
+
+        if let Some(velocity_a) = &mut velocity_a {
+            velocity_a.0 -= impulse * inverse_mass_a;
+        }
+        if let Some(velocity_b) = &mut velocity_b {
+            velocity_b.0 += impulse * inverse_mass_b;
+        }
+    }
+}
+
+/// Normal pointing from `a` to `b` and penetration depth of two overlapping bodies
+fn contact(a: &Body, b: &Body) -> Option<(Vec2f, Float)> {
+    match (a.collider.shape, b.collider.shape) {
+        (
+            ColliderShape::Circle { radius: radius_a },
+            ColliderShape::Circle { radius: radius_b },
+        ) => circle_circle(a.position, radius_a, b.position, radius_b),
+        (ColliderShape::Circle { radius }, ColliderShape::Aabb { .. }) => {
+            circle_aabb(a.position, radius, b).map(|(normal, penetration)| (-normal, penetration))
+        }
+        (ColliderShape::Aabb { .. }, ColliderShape::Circle { radius }) => {
+            circle_aabb(b.position, radius, a)
+        }
+        (ColliderShape::Aabb { .. }, ColliderShape::Aabb { .. }) => aabb_aabb(a, b),
+    }
+}
+
+fn circle_circle(
+    position_a: Vec2f,
+    radius_a: Float,
+    position_b: Vec2f,
+    radius_b: Float,
+) -> Option<(Vec2f, Float)> {
+    let offset = position_b - position_a;
+    let radii = radius_a + radius_b;
+    let distance_squared = offset.dot(&offset);
+    if distance_squared >= radii * radii {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    // Concentric circles are pushed apart along an arbitrary but fixed axis
+    let normal = if distance > Float::from_num(0) {
+        offset / distance
+    } else {
+        Vec2f::from_num(1, 0)
+    };
+    Some((normal, radii - distance))
+}
+
+/// Normal pointing from the box to the circle
+fn circle_aabb(circle_position: Vec2f, radius: Float, aabb: &Body) -> Option<(Vec2f, Float)> {
+    let closest = Vec2f::new(
+        clamp(circle_position.x, aabb.min.x, aabb.max.x),
+        clamp(circle_position.y, aabb.min.y, aabb.max.y),
+    );
+    if closest == circle_position {
+        // The center lies inside the box, push it out through the nearest face
+        let (normal, depth) = aabb_face(circle_position, aabb);
+        return Some((normal, depth + radius));
+    }
+    let offset = circle_position - closest;
+    let distance_squared = offset.dot(&offset);
+    if distance_squared >= radius * radius {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    if distance == Float::from_num(0) {
+        // Too close to the box to tell the direction apart
+        let (normal, depth) = aabb_face(circle_position, aabb);
+        return Some((normal, depth + radius));
+    }
+    Some((offset / distance, radius - distance))
+}
+
+fn aabb_aabb(a: &Body, b: &Body) -> Option<(Vec2f, Float)> {
+    let overlap_x = min(a.max.x, b.max.x) - max(a.min.x, b.min.x);
+    let overlap_y = min(a.max.y, b.max.y) - max(a.min.y, b.min.y);
+    let zero = Float::from_num(0);
+    if overlap_x <= zero || overlap_y <= zero {
+        return None;
+    }
+    let one = Float::from_num(1);
+    // Separate along the axis of the smallest overlap
+    if overlap_x < overlap_y {
+        let sign = if b.position.x < a.position.x {
+            -one
+        } else {
+            one
+        };
+        Some((Vec2f::new(sign, zero), overlap_x))
+    } else {
+        let sign = if b.position.y < a.position.y {
+            -one
+        } else {
+            one
+        };
+        Some((Vec2f::new(zero, sign), overlap_y))
+    }
+}
+
+/// Outward normal of the face of the box that `point` is closest to and the distance to it
+fn aabb_face(point: Vec2f, aabb: &Body) -> (Vec2f, Float) {
+    let zero = Float::from_num(0);
+    let one = Float::from_num(1);
+    let faces = [
+        (Vec2f::new(-one, zero), point.x - aabb.min.x),
+        (Vec2f::new(one, zero), aabb.max.x - point.x),
+        (Vec2f::new(zero, -one), point.y - aabb.min.y),
+        (Vec2f::new(zero, one), aabb.max.y - point.y),
+    ];
+    faces
+        .into_iter()
+        .reduce(|nearest, face| if face.1 < nearest.1 { face } else { nearest })
+        .expect("a box to have faces")
+}
+
+fn min(a: Float, b: Float) -> Float {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+fn max(a: Float, b: Float) -> Float {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+fn clamp(value: Float, low: Float, high: Float) -> Float {
+    max(low, min(value, high))
+}
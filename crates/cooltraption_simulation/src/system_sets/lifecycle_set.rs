@@ -0,0 +1,296 @@
+use bevy_ecs::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{entity_order, Lifetime, NetId, Position, SpawnedAt, Velocity};
+use crate::system_sets::physics_set::{Float, FromNum2, Vec2f};
+use crate::Tick;
+
+/// What happens to entities that leave the world bounds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    /// Moves the entity back onto the border and stops it there
+    Clamp,
+    /// Moves the entity to the opposite border
+    Wrap,
+    /// Moves the entity back onto the border and reflects its velocity
+    Bounce,
+    Kill,
+}
+
+/// Rectangle that entities with a `Position` are confined to at the end of every tick
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldBounds {
+    pub min: Vec2f,
+    pub max: Vec2f,
+    pub mode: BoundaryMode,
+}
+
+impl WorldBounds {
+    /// Bounds centered on the origin
+    pub fn centered(half_extents: Vec2f, mode: BoundaryMode) -> Self {
+        Self {
+            min: -half_extents,
+            max: half_extents,
+            mode,
+        }
+    }
+}
+
+/// Which entities are despawned once there are more than the cap allows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CapPolicy {
+    /// Entities spawned beyond the cap are despawned in the tick they were spawned in
+    RejectNewest,
+    DespawnOldest,
+}
+
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityCap {
+    pub max_entities: usize,
+    pub policy: CapPolicy,
+}
+
+/// Every lifecycle system runs in this set, after the entities moved and collided
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct LifecycleSet;
+
+/// Counts down the lifetimes and despawns the entities whose lifetime ran out
+pub fn expire_lifetimes(world: &mut World) {
+    let mut expired = vec![];
+    let mut query = world.query::<(Entity, &mut Lifetime)>();
+    for (entity, mut lifetime) in query.iter_mut(world) {
+        lifetime.0 = lifetime.0.saturating_sub(1);
+        if lifetime.0 == 0 {
+            expired.push(entity);
+        }
+    }
+    despawn_all(world, expired);
+}
+
+pub fn enforce_world_bounds(world: &mut World) {
+    let Some(bounds) = world.get_resource::<WorldBounds>().copied() else {
+        return;
+    };
+    let mut killed = vec![];
+    let mut query = world.query::<(Entity, &mut Position, Option<&mut Velocity>)>();
+    for (entity, mut position, velocity) in query.iter_mut(world) {
+        let mut new_position = position.0;
+        let mut new_velocity = velocity
+            .as_ref()
+            .map_or(Vec2f::from_num(0, 0), |velocity| velocity.0);
+        let inside_x = confine(
+            bounds.mode,
+            bounds.min.x,
+            bounds.max.x,
+            &mut new_position.x,
+            &mut new_velocity.x,
+        );
+        let inside_y = confine(
+            bounds.mode,
+            bounds.min.y,
+            bounds.max.y,
+            &mut new_position.y,
+            &mut new_velocity.y,
+        );
+        if inside_x && inside_y {
+            continue;
+        }
+        if bounds.mode == BoundaryMode::Kill {
+            killed.push(entity);
+            continue;
+        }
+        position.0 = new_position;
+        if let Some(mut velocity) = velocity {
+            velocity.0 = new_velocity;
+        }
+    }
+    despawn_all(world, killed);
+}
+
+/// Despawns the oldest or newest entities, depending on the policy, until the cap is met
+pub fn enforce_entity_cap(world: &mut World) {
+    let Some(cap) = world.get_resource::<EntityCap>().copied() else {
+        return;
+    };
+    let mut entities: Vec<((Tick, (u64, u64)), Entity)> = world
+        .query::<(Entity, Option<&SpawnedAt>, Option<&NetId>)>()
+        .iter(world)
+        .map(|(entity, spawned_at, net_id)| {
+            let spawned_at = spawned_at.map_or(Tick(0), |spawned_at| spawned_at.0);
+            ((spawned_at, entity_order(entity, net_id)), entity)
+        })
+        .collect();
+    let Some(excess) = entities.len().checked_sub(cap.max_entities) else {
+        return;
+    };
+    entities.sort_by_key(|(order, _)| *order);
+    let despawned = match cap.policy {
+        CapPolicy::RejectNewest => entities.split_off(entities.len() - excess),
+        CapPolicy::DespawnOldest => entities.drain(..excess).collect(),
+    };
+    despawn_all(
+        world,
+        despawned.into_iter().map(|(_, entity)| entity).collect(),
+    );
+}
+
+/// Returns whether the coordinate was within the bounds, moves it back in otherwise
+fn confine(
+    mode: BoundaryMode,
+    min: Float,
+    max: Float,
+    position: &mut Float,
+    velocity: &mut Float,
+) -> bool {
+    if *position >= min && *position <= max {
+        return true;
+    }
+    let zero = Float::from_num(0);
+    match mode {
+        BoundaryMode::Clamp => {
+            *position = if *position < min { min } else { max };
+            *velocity = zero;
+        }
+        BoundaryMode::Wrap => {
+            let width = max - min;
+            if width > zero {
+                // Converting to an integer rounds towards negative infinity
+                let wraps = Float::from_num(((*position - min) / width).0.to_num::<i64>());
+                *position -= width * wraps;
+            } else {
+                *position = min;
+            }
+        }
+        BoundaryMode::Bounce => {
+            if *position < min {
+                *position = min;
+                if *velocity < zero {
+                    *velocity = -*velocity;
+                }
+            } else {
+                *position = max;
+                if *velocity > zero {
+                    *velocity = -*velocity;
+                }
+            }
+        }
+        BoundaryMode::Kill => {}
+    }
+    false
+}
+
+/// Despawns in the same order on every peer, `entities` may contain an entity more than once
+pub fn despawn_all(world: &mut World, mut entities: Vec<Entity>) {
+    entities.sort_by_key(|entity| entity_order(*entity, world.get::<NetId>(*entity)));
+    entities.dedup();
+    for entity in entities {
+        world.despawn(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entities_are_despawned_once_their_lifetime_ran_out() {
+        let mut world = World::new();
+        let short_lived = world.spawn(Lifetime(1)).id();
+        let long_lived = world.spawn(Lifetime(2)).id();
+        let immortal = world.spawn(Position(Vec2f::from_num(0, 0))).id();
+
+        expire_lifetimes(&mut world);
+        assert!(world.get_entity(short_lived).is_none());
+        assert_eq!(world.get::<Lifetime>(long_lived), Some(&Lifetime(1)));
+
+        expire_lifetimes(&mut world);
+        assert!(world.get_entity(long_lived).is_none());
+        assert!(world.get_entity(immortal).is_some());
+    }
+
+    #[test]
+    fn bounce_reflects_entities_back_into_the_bounds() {
+        let mut world = World::new();
+        world.insert_resource(WorldBounds::centered(
+            Vec2f::from_num(10, 10),
+            BoundaryMode::Bounce,
+        ));
+        let outside = world
+            .spawn((
+                Position(Vec2f::from_num(12, 0)),
+                Velocity(Vec2f::from_num(3, 1)),
+            ))
+            .id();
+        let inside = world
+            .spawn((
+                Position(Vec2f::from_num(-5, 5)),
+                Velocity(Vec2f::from_num(3, 1)),
+            ))
+            .id();
+
+        enforce_world_bounds(&mut world);
+        assert_eq!(
+            world.get::<Position>(outside).unwrap().0,
+            Vec2f::from_num(10, 0)
+        );
+        assert_eq!(
+            world.get::<Velocity>(outside).unwrap().0,
+            Vec2f::from_num(-3, 1)
+        );
+        assert_eq!(
+            world.get::<Position>(inside).unwrap().0,
+            Vec2f::from_num(-5, 5)
+        );
+        assert_eq!(
+            world.get::<Velocity>(inside).unwrap().0,
+            Vec2f::from_num(3, 1)
+        );
+    }
+
+    #[test]
+    fn kill_despawns_entities_outside_the_bounds() {
+        let mut world = World::new();
+        world.insert_resource(WorldBounds::centered(
+            Vec2f::from_num(10, 10),
+            BoundaryMode::Kill,
+        ));
+        let outside = world.spawn(Position(Vec2f::from_num(0, -11))).id();
+        let on_border = world.spawn(Position(Vec2f::from_num(10, -10))).id();
+
+        enforce_world_bounds(&mut world);
+        assert!(world.get_entity(outside).is_none());
+        assert!(world.get_entity(on_border).is_some());
+    }
+
+    fn spawn_capped(world: &mut World, policy: CapPolicy) -> [Entity; 3] {
+        world.insert_resource(EntityCap {
+            max_entities: 1,
+            policy,
+        });
+        [(5, 0), (3, 1), (5, 2)]
+            .map(|(tick, net_id)| world.spawn((SpawnedAt(Tick(tick)), NetId(net_id))).id())
+    }
+
+    #[test]
+    fn despawn_oldest_keeps_the_newest_entities() {
+        let mut world = World::new();
+        let [first, oldest, last] = spawn_capped(&mut world, CapPolicy::DespawnOldest);
+
+        enforce_entity_cap(&mut world);
+        // Entities spawned in the same tick are ordered by their `NetId`
+        assert!(world.get_entity(oldest).is_none());
+        assert!(world.get_entity(first).is_none());
+        assert!(world.get_entity(last).is_some());
+    }
+
+    #[test]
+    fn reject_newest_keeps_the_oldest_entities() {
+        let mut world = World::new();
+        let [first, oldest, last] = spawn_capped(&mut world, CapPolicy::RejectNewest);
+
+        enforce_entity_cap(&mut world);
+        assert!(world.get_entity(oldest).is_some());
+        assert!(world.get_entity(first).is_none());
+        assert!(world.get_entity(last).is_none());
+    }
+}
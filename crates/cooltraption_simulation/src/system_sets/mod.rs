@@ -1,2 +1,4 @@
+pub mod collision_set;
+pub mod lifecycle_set;
 pub mod physics_set;
-pub mod action_set;
+pub mod spatial_set;
@@ -8,8 +8,9 @@ use nalgebra::{Matrix2, Vector2};
 
 use simba::scalar::FixedI48F16 as I48F16;
 
-use crate::components::{Acceleration, Position, Velocity};
-use derive_more::{Deref, From};
+use crate::clock::TickRate;
+use crate::components::{Acceleration, Drag, Force, Position, Velocity, Weight};
+use serde::{Deserialize, Serialize};
 
 pub type Float = I48F16;
 pub type Vec2f = Vector2<Float>;
@@ -39,29 +40,88 @@ impl FromNum4 for Mat2f {
     }
 }
 
-#[derive(Resource, Default, Deref, From)]
-pub struct DeltaTime(Duration);
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum DeltaTime {
+    Fixed(TickRate),
+    Measured(Duration),
+}
+
+impl Default for DeltaTime {
+    fn default() -> Self {
+        DeltaTime::Measured(Duration::ZERO)
+    }
+}
+
+impl From<Duration> for DeltaTime {
+    fn from(duration: Duration) -> Self {
+        DeltaTime::Measured(duration)
+    }
+}
+
+impl From<TickRate> for DeltaTime {
+    fn from(tick_rate: TickRate) -> Self {
+        DeltaTime::Fixed(tick_rate)
+    }
+}
+
 impl DeltaTime {
     pub fn seconds(&self) -> Float {
-        Float::from_num(self.0.as_secs_f64())
+        match self {
+            DeltaTime::Fixed(tick_rate) => {
+                Float::from_num(1) / Float::from_num(tick_rate.ticks_per_second())
+            }
+            DeltaTime::Measured(duration) => Float::from_num(duration.as_secs_f64()),
+        }
     }
 
     pub fn milliseconds(&self) -> Float {
-        Float::from_num(self.0.as_micros() / 1000)
+        match self {
+            DeltaTime::Fixed(tick_rate) => {
+                Float::from_num(1000) / Float::from_num(tick_rate.ticks_per_second())
+            }
+            DeltaTime::Measured(duration) => Float::from_num(duration.as_micros() / 1000),
+        }
     }
 
     pub fn nanoseconds(&self) -> u128 {
-        self.0.as_nanos()
+        self.duration().as_nanos()
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            DeltaTime::Fixed(tick_rate) => tick_rate.tick_duration(),
+            DeltaTime::Measured(duration) => *duration,
+        }
     }
 }
 
+/// Integrates with semi-implicit Euler, the new velocity moves the entity in the same tick.
+/// The accumulated forces are cleared afterwards.
 pub fn solve_movement(
-    mut query: Query<(&mut Position, &mut Velocity, &mut Acceleration)>,
+    mut query: Query<(
+        &mut Position,
+        &mut Velocity,
+        &Acceleration,
+        Option<&Weight>,
+        Option<&mut Force>,
+        Option<&Drag>,
+    )>,
     dt: Res<DeltaTime>,
 ) {
-    for (mut pos, mut vel, acc) in &mut query {
-        vel.0 += acc.0 * dt.seconds();
-        pos.0 += vel.0 * dt.seconds();
+    let dt = dt.seconds();
+    let zero = Float::from_num(0);
+    for (mut pos, mut vel, acc, weight, force, drag) in &mut query {
+        let mut acceleration = acc.0;
+        if let Some(mut force) = force {
+            acceleration += force.0 * weight.copied().unwrap_or_default().inverse();
+            force.0 = Vec2f::from_num(0, 0);
+        }
+        vel.0 += acceleration * dt;
+        if let Some(drag) = drag {
+            let damping = Float::from_num(1) - drag.0 * dt;
+            vel.0 *= if damping > zero { damping } else { zero };
+        }
+        pos.0 += vel.0 * dt;
     }
 }
 
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use bevy_ecs::prelude::*;
+
+use crate::components::{entity_order, NetId, Position};
+use crate::system_sets::physics_set::{Float, Vec2f};
+
+/// Grid of square cells that every entity with a `Position` is sorted into. It reflects the
+/// positions at the time `update_spatial_hash` last ran, so schedules rebuild it after moving.
+/// Queries visit the cells and the entities within them in a fixed order, so their results
+/// are the same on every peer.
+#[derive(Resource, Clone, Debug)]
+pub struct SpatialHash {
+    cell_size: Float,
+    cells: HashMap<(i64, i64), Vec<Entry>>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    order: (u64, u64),
+    entity: Entity,
+    position: Vec2f,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(Float::from_num(2))
+    }
+}
+
+impl SpatialHash {
+    /// Queries are fastest with cells about as large as their typical radius
+    pub fn new(cell_size: Float) -> Self {
+        Self {
+            cell_size,
+            cells: Default::default(),
+        }
+    }
+
+    pub fn rebuild(&mut self, entities: impl Iterator<Item = (Entity, Option<NetId>, Vec2f)>) {
+        self.cells.clear();
+        for (entity, net_id, position) in entities {
+            let cell = self.cell_of(position);
+            self.cells.entry(cell).or_default().push(Entry {
+                order: entity_order(entity, net_id.as_ref()),
+                entity,
+                position,
+            });
+        }
+        for cell in self.cells.values_mut() {
+            cell.sort_by_key(|entry| entry.order);
+        }
+    }
+
+    /// Entities whose position is at most `radius` away from `center`
+    pub fn within_radius(&self, center: Vec2f, radius: Float) -> Vec<Entity> {
+        let radius_squared = radius * radius;
+        let extent = Vec2f::new(radius, radius);
+        self.collect(center - extent, center + extent, |position| {
+            let offset = position - center;
+            offset.dot(&offset) <= radius_squared
+        })
+    }
+
+    /// Entities whose position lies within the rectangle spanned by `min` and `max`
+    pub fn in_rect(&self, min: Vec2f, max: Vec2f) -> Vec<Entity> {
+        self.collect(min, max, |position| {
+            position.x >= min.x && position.x <= max.x && position.y >= min.y && position.y <= max.y
+        })
+    }
+
+    fn collect(&self, min: Vec2f, max: Vec2f, contains: impl Fn(Vec2f) -> bool) -> Vec<Entity> {
+        let (min_x, min_y) = self.cell_of(min);
+        let (max_x, max_y) = self.cell_of(max);
+        let covered_cells =
+            (max_x as i128 - min_x as i128 + 1) * (max_y as i128 - min_y as i128 + 1);
+        // Large areas are cheaper to answer from the occupied cells than from every covered one
+        let cells: Vec<(i64, i64)> = if covered_cells > self.cells.len() as i128 {
+            let mut cells: Vec<_> = self
+                .cells
+                .keys()
+                .filter(|(x, y)| (min_x..=max_x).contains(x) && (min_y..=max_y).contains(y))
+                .copied()
+                .collect();
+            cells.sort();
+            cells
+        } else {
+            (min_x..=max_x)
+                .flat_map(|x| (min_y..=max_y).map(move |y| (x, y)))
+                .collect()
+        };
+
+        let mut entities = vec![];
+        for cell in cells {
+            let Some(cell) = self.cells.get(&cell) else {
+                continue;
+            };
+            entities.extend(
+                cell.iter()
+                    .filter(|entry| contains(entry.position))
+                    .map(|entry| entry.entity),
+            );
+        }
+        entities
+    }
+
+    fn cell_of(&self, position: Vec2f) -> (i64, i64) {
+        // Converting to an integer rounds towards negative infinity
+        (
+            (position.x / self.cell_size).0.to_num::<i64>(),
+            (position.y / self.cell_size).0.to_num::<i64>(),
+        )
+    }
+}
+
+pub fn update_spatial_hash(
+    query: Query<(Entity, &Position, Option<&NetId>)>,
+    mut spatial_hash: ResMut<SpatialHash>,
+) {
+    spatial_hash.rebuild(
+        query
+            .iter()
+            .map(|(entity, position, net_id)| (entity, net_id.copied(), position.0)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_sets::physics_set::FromNum2;
+
+    fn spatial_hash() -> (SpatialHash, [Entity; 5]) {
+        let entities = [0, 1, 2, 3, 4].map(Entity::from_raw);
+        let positions = [
+            Vec2f::from_num(0, 0),
+            Vec2f::from_num(3, 0),
+            Vec2f::from_num(-1, -1),
+            Vec2f::from_num(1.5, 1.5),
+            Vec2f::from_num(100, 100),
+        ];
+        let mut spatial_hash = SpatialHash::new(Float::from_num(2));
+        // Inserted in reverse, the results are ordered by `NetId` regardless
+        spatial_hash.rebuild(
+            entities
+                .into_iter()
+                .zip(positions)
+                .enumerate()
+                .rev()
+                .map(|(index, (entity, position))| (entity, Some(NetId(index as u64)), position)),
+        );
+        (spatial_hash, entities)
+    }
+
+    #[test]
+    fn within_radius_includes_the_boundary() {
+        let (spatial_hash, [e0, e1, e2, e3, _]) = spatial_hash();
+        assert_eq!(
+            spatial_hash.within_radius(Vec2f::from_num(0, 0), Float::from_num(3)),
+            vec![e2, e0, e3, e1]
+        );
+        assert_eq!(
+            spatial_hash.within_radius(Vec2f::from_num(0, 0), Float::from_num(2.9)),
+            vec![e2, e0, e3]
+        );
+        assert!(spatial_hash
+            .within_radius(Vec2f::from_num(50, 50), Float::from_num(10))
+            .is_empty());
+    }
+
+    #[test]
+    fn in_rect_finds_entities_in_small_and_large_areas() {
+        let (spatial_hash, [e0, _, e2, _, e4]) = spatial_hash();
+        assert_eq!(
+            spatial_hash.in_rect(Vec2f::from_num(-1, -1), Vec2f::from_num(1, 1)),
+            vec![e2, e0]
+        );
+        // Covers more cells than are occupied, so only the occupied ones are visited
+        assert_eq!(
+            spatial_hash.in_rect(Vec2f::from_num(50, 50), Vec2f::from_num(150, 150)),
+            vec![e4]
+        );
+        assert_eq!(
+            spatial_hash
+                .in_rect(Vec2f::from_num(-1000, -1000), Vec2f::from_num(1000, 1000))
+                .len(),
+            5
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
+
+use crate::action::{Action, ActionType};
+use crate::components::Position;
+use crate::system_sets::physics_set::Float;
+
+/// Limits every action of a player has to stay within, offending actions are dropped.
+/// The bounds and strength limits apply to the actions whose `ActionChecks` use them.
+#[derive(Debug, Clone, Serialize, Deserialize, SmartDefault)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ActionRules {
+    /// Actions have to target positions within this distance from the origin on both axes
+    #[default(10_000.0)]
+    pub world_extent: f32,
+    #[default(100.0)]
+    pub max_force_strength: f32,
+    /// Actions above this rate are throttled
+    #[default(20)]
+    pub max_actions_per_second: u32,
+    /// Players are disconnected after sending this many invalid actions or forbidden packets
+    #[default(10)]
+    pub max_violations: u32,
+}
+
+impl ActionRules {
+    pub fn check_bounds(&self, position: &Position) -> Result<(), ValidationError> {
+        let extent = Float::from_num(self.world_extent);
+        let (x, y) = (position.0.x, position.0.y);
+        if x < -extent || x > extent || y < -extent || y > extent {
+            return Err(ValidationError::OutOfBounds { x, y });
+        }
+        Ok(())
+    }
+
+    pub fn check_strength(&self, strength: Float) -> Result<(), ValidationError> {
+        let max_strength = Float::from_num(self.max_force_strength);
+        if strength < -max_strength || strength > max_strength {
+            return Err(ValidationError::TooStrong { strength });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidationError {
+    OutOfBounds { x: Float, y: Float },
+    TooStrong { strength: Float },
+    Undecodable { name: String },
+    RateLimited,
+}
+
+impl ValidationError {
+    /// Throttled actions are dropped without counting against the player
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, ValidationError::RateLimited)
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::OutOfBounds { x, y } => {
+                write!(f, "position ({}, {}) is out of bounds", x, y)
+            }
+            ValidationError::TooStrong { strength } => {
+                write!(f, "force strength {} exceeds the limit", strength)
+            }
+            ValidationError::Undecodable { name } => write!(f, "could not decode {} action", name),
+            ValidationError::RateLimited => write!(f, "too many actions per second"),
+        }
+    }
+}
+
+type ActionCheck = Box<dyn Fn(&Action, &ActionRules) -> Result<(), ValidationError> + Send>;
+
+/// Checks of the action types of a game, keyed by `ActionType::NAME`.
+/// Actions without a check are rejected, `add` a check that accepts everything to allow them.
+#[derive(Default)]
+pub struct ActionChecks {
+    checks: HashMap<&'static str, ActionCheck>,
+}
+
+impl ActionChecks {
+    /// Replaces the previous check of `A`
+    pub fn add<A: ActionType>(
+        &mut self,
+        check: impl Fn(&A, &ActionRules) -> Result<(), ValidationError> + Send + 'static,
+    ) -> &mut Self {
+        self.checks.insert(
+            A::NAME,
+            Box::new(move |action, rules| {
+                let decoded = action
+                    .decode::<A>()
+                    .ok_or_else(|| ValidationError::Undecodable {
+                        name: action.name.clone(),
+                    })?;
+                check(&decoded, rules)
+            }),
+        );
+        self
+    }
+
+    pub fn check(&self, action: &Action, rules: &ActionRules) -> Result<(), ValidationError> {
+        match self.checks.get(action.name.as_str()) {
+            Some(check) => check(action, rules),
+            None => Err(ValidationError::Undecodable {
+                name: action.name.clone(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use cooltraption_common::types::PlayerId;
+use cooltraption_simulation::harness::SimulationHarness;
+use cooltraption_simulation::system_sets::collision_set::{self, Collider, Collisions};
+use cooltraption_simulation::system_sets::physics_set::{self, Float, FromNum2, PhysicsSet, Vec2f};
+use cooltraption_simulation::{
+    Acceleration, Drag, Force, IntoSystemConfig, IntoSystemConfigs, NetIds, Owner, PhysicsBundle,
+    Position, Schedule, Velocity, Weight,
+};
+
+fn schedule() -> Schedule {
+    let mut schedule = Schedule::default();
+    schedule.add_system(physics_set::solve_movement.in_set(PhysicsSet::Movement));
+    schedule.add_systems(
+        (
+            collision_set::detect_collisions,
+            collision_set::resolve_collisions,
+        )
+            .chain()
+            .in_set(PhysicsSet::CollisionDetection)
+            .after(PhysicsSet::Movement),
+    );
+    schedule
+}
+
+fn spawn_ball(harness: &mut SimulationHarness, player: PlayerId, x: i32, velocity_x: i32) {
+    let world = harness.state_mut().world_mut();
+    let net_id = world.resource_mut::<NetIds>().allocate();
+    world.spawn((
+        PhysicsBundle {
+            acc: Acceleration::default(),
+            vel: Velocity(Vec2f::from_num(velocity_x, 0)),
+            pos: Position(Vec2f::from_num(x, 0)),
+            weight: Weight::default(),
+            force: Force::default(),
+            drag: Drag(Float::from_num(0)),
+        },
+        Owner(player),
+        Collider::circle(Float::from_num(0.5)),
+        net_id,
+    ));
+}
+
+/// Position and velocity along the x axis of the balls of both players
+fn balls(harness: &mut SimulationHarness) -> [(Float, Float); 2] {
+    let mut balls = [(Float::from_num(0), Float::from_num(0)); 2];
+    harness
+        .state_mut()
+        .query::<(&Owner, &Position, &Velocity)>(|iter| {
+            for (owner, position, velocity) in iter {
+                balls[owner.0 .0 as usize] = (position.0.x, velocity.0.x);
+            }
+        });
+    balls
+}
+
+#[test]
+fn colliding_balls_bounce_off_each_other() {
+    let mut harness = SimulationHarness::new(schedule());
+    spawn_ball(&mut harness, PlayerId(0), -2, 3);
+    spawn_ball(&mut harness, PlayerId(1), 2, -3);
+
+    let mut collided = false;
+    for _ in 0..60 {
+        harness.advance(1);
+        collided |= !harness
+            .state()
+            .world()
+            .resource::<Collisions>()
+            .0
+            .is_empty();
+    }
+    assert!(collided);
+
+    let [(left_x, left_velocity), (right_x, right_velocity)] = balls(&mut harness);
+    assert!(left_x < right_x);
+    assert!(left_velocity < Float::from_num(0));
+    assert!(right_velocity > Float::from_num(0));
+    // Equal masses collide symmetrically and lose energy to the restitution below 1
+    assert_eq!(left_velocity, -right_velocity);
+    assert!(right_velocity < Float::from_num(3));
+}
+
+#[test]
+fn separated_balls_do_not_collide() {
+    let mut harness = SimulationHarness::new(schedule());
+    spawn_ball(&mut harness, PlayerId(0), -2, 0);
+    spawn_ball(&mut harness, PlayerId(1), 2, 0);
+    harness.advance(10);
+
+    assert!(harness
+        .state()
+        .world()
+        .resource::<Collisions>()
+        .0
+        .is_empty());
+    let [(left_x, _), (right_x, _)] = balls(&mut harness);
+    assert_eq!(left_x, Float::from_num(-2));
+    assert_eq!(right_x, Float::from_num(2));
+}
@@ -0,0 +1,168 @@
+use cooltraption_common::types::PlayerId;
+use cooltraption_simulation::action::{Action, ActionRegistry, ActionSet, ActionType, ActionsOf};
+use cooltraption_simulation::harness::SimulationHarness;
+use cooltraption_simulation::simulation_state::SimulationState;
+use cooltraption_simulation::system_sets::collision_set::{self, Collider};
+use cooltraption_simulation::system_sets::physics_set::{self, Float, FromNum2, PhysicsSet, Vec2f};
+use cooltraption_simulation::{
+    apply_system_buffers, Acceleration, Commands, Drag, Force, IntoSystemConfig, IntoSystemConfigs,
+    IntoSystemSetConfig, NetIds, Owner, PhysicsBundle, Position, Res, ResMut, Schedule, Tick,
+    Velocity, Weight,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct SpawnBallAction {
+    position: (i32, i32),
+    velocity: (i32, i32),
+}
+
+impl ActionType for SpawnBallAction {
+    const NAME: &'static str = "spawn_ball";
+}
+
+fn spawn_balls(
+    actions: Res<ActionsOf<SpawnBallAction>>,
+    mut net_ids: ResMut<NetIds>,
+    mut commands: Commands,
+) {
+    for (player, spawn_ball) in &actions.0 {
+        let (x, y) = spawn_ball.position;
+        let (velocity_x, velocity_y) = spawn_ball.velocity;
+        commands.spawn((
+            PhysicsBundle {
+                acc: Acceleration::default(),
+                vel: Velocity(Vec2f::from_num(velocity_x, velocity_y)),
+                pos: Position(Vec2f::from_num(x, y)),
+                weight: Weight::default(),
+                force: Force::default(),
+                drag: Drag(Float::from_num(0)),
+            },
+            Owner(*player),
+            Collider::circle(Float::from_num(0.5)),
+            net_ids.allocate(),
+        ));
+    }
+}
+
+fn schedule() -> Schedule {
+    let mut schedule = Schedule::default();
+    schedule.add_system(physics_set::solve_movement.in_set(PhysicsSet::Movement));
+    schedule.configure_set(ActionSet.before(PhysicsSet::Movement));
+    schedule.add_system(
+        apply_system_buffers
+            .after(ActionSet)
+            .before(PhysicsSet::Movement),
+    );
+    schedule.add_systems(
+        (
+            collision_set::detect_collisions,
+            collision_set::resolve_collisions,
+        )
+            .chain()
+            .in_set(PhysicsSet::CollisionDetection)
+            .after(PhysicsSet::Movement),
+    );
+    ActionRegistry::default().register::<SpawnBallAction, _>(&mut schedule, spawn_balls);
+    schedule
+}
+
+/// Two balls of different players that collide head on, and a third one that hits them later
+fn push_actions(harness: &mut SimulationHarness, players: [PlayerId; 2]) {
+    let [first, second] = players;
+    harness
+        .push_action(
+            Tick(1),
+            first,
+            Action::new(&SpawnBallAction {
+                position: (-3, 0),
+                velocity: (4, 0),
+            }),
+        )
+        .push_action(
+            Tick(1),
+            second,
+            Action::new(&SpawnBallAction {
+                position: (3, 0),
+                velocity: (-4, 0),
+            }),
+        )
+        .push_action(
+            Tick(5),
+            first,
+            Action::new(&SpawnBallAction {
+                position: (0, 4),
+                velocity: (0, -5),
+            }),
+        );
+}
+
+#[test]
+fn rolled_back_run_matches_straight_run() {
+    let players = [PlayerId(0), PlayerId(1)];
+
+    let mut straight = SimulationHarness::new(schedule());
+    push_actions(&mut straight, players);
+    straight.advance_to(Tick(60));
+
+    let mut rolled_back = SimulationHarness::new(schedule());
+    rolled_back.advance_to(Tick(20));
+    push_actions(&mut rolled_back, players);
+    rolled_back.advance_to(Tick(60));
+
+    assert_eq!(straight.current_tick(), rolled_back.current_tick());
+    assert_eq!(straight.checksum(), rolled_back.checksum());
+}
+
+#[test]
+fn arrival_order_of_actions_does_not_matter() {
+    let mut first = SimulationHarness::new(schedule());
+    push_actions(&mut first, [PlayerId(0), PlayerId(1)]);
+    first.advance_to(Tick(60));
+
+    // Same actions, but the packets of the second player arrive first
+    let mut second = SimulationHarness::new(schedule());
+    second
+        .push_action(
+            Tick(1),
+            PlayerId(1),
+            Action::new(&SpawnBallAction {
+                position: (3, 0),
+                velocity: (-4, 0),
+            }),
+        )
+        .push_action(
+            Tick(1),
+            PlayerId(0),
+            Action::new(&SpawnBallAction {
+                position: (-3, 0),
+                velocity: (4, 0),
+            }),
+        )
+        .push_action(
+            Tick(5),
+            PlayerId(0),
+            Action::new(&SpawnBallAction {
+                position: (0, 4),
+                velocity: (0, -5),
+            }),
+        );
+    second.advance_to(Tick(60));
+
+    assert_eq!(first.checksum(), second.checksum());
+}
+
+#[test]
+fn snapshot_round_trip_is_byte_stable() {
+    let mut harness = SimulationHarness::new(schedule());
+    push_actions(&mut harness, [PlayerId(0), PlayerId(1)]);
+    harness.advance_to(Tick(30));
+    let snapshot = harness.snapshot();
+
+    let mut restored = SimulationState::default();
+    restored.restore(&snapshot).unwrap();
+
+    assert_eq!(restored.current_tick(), Tick(30));
+    assert_eq!(restored.snapshot().as_bytes(), snapshot.as_bytes());
+    assert_eq!(restored.checksum(), harness.checksum());
+}
@@ -5,6 +5,7 @@ use crate::events::{Context, Event, EventHandler};
 pub use winit;
 use winit::dpi::PhysicalSize;
 use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy};
+use winit::platform::run_return::EventLoopExtRunReturn;
 use winit::window::{Window, WindowBuilder};
 
 pub use self::window_event_handler::WindowEventHandler;
@@ -60,12 +61,13 @@ impl WinitEventLoopHandler {
         self.handlers.push(handler);
     }
 
-    pub fn run_event_loop(mut self) -> ! {
+    /// Returns once a handler sets `ControlFlow::Exit`
+    pub fn run_event_loop(mut self) {
         self.event_loop_proxy
             .send_event(WindowEvent::Init)
             .expect("Send init event");
 
-        self.event_loop.run(move |mut event, _, control_flow| {
+        self.event_loop.run_return(|mut event, _, control_flow| {
             *control_flow = ControlFlow::Wait;
 
             let mut new_event_handlers = vec![];